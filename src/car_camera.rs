@@ -1,20 +1,41 @@
 use bevy::prelude::*;
 use bevy_rapier3d::prelude::*;
-use crate::car_suspension::CarPhysics;
+use crate::{car_suspension::CarPhysics, vehicle_input::HumanDriver};
 #[derive(Component)]
 pub struct CameraFollow {
+    /// Car the camera follows, picked by `assign_camera_target` when left empty.
+    pub target: Option<Entity>,
     pub camera_translation_speed: f32,
     pub fake_transform: Transform,
     pub distance_behind: f32,
 }
+/// Points cameras without a target, or whose target was despawned, at the first car
+/// driven by a player, or any car when nobody is driving.
+pub fn assign_camera_target(
+    car_query: Query<(Entity, Option<&HumanDriver>), With<CarPhysics>>,
+    mut camera_query: Query<&mut CameraFollow>,
+) {
+    for mut camera_follow in camera_query.iter_mut() {
+        if camera_follow.target.is_none_or(|target| !car_query.contains(target)) {
+            camera_follow.target = car_query
+                .iter()
+                .find(|(_, human_driver)| human_driver.is_some())
+                .or_else(|| car_query.iter().next())
+                .map(|(entity, _)| entity);
+        }
+    }
+}
 pub fn camera_follow(
     time: Res<Time>,
     rapier_context: Res<RapierContext>,
-    mut car_query: Query<(&mut CarPhysics, &mut Transform), Without<CameraFollow>>,
+    car_query: Query<(&CarPhysics, &Transform), Without<CameraFollow>>,
     mut camera_query: Query<(&mut CameraFollow, &mut Transform), Without<CarPhysics>>,
 ) {
-    if let Ok((mut camera_follow, mut camera_transform)) = camera_query.get_single_mut() {
-        if let Ok((car_physics, car_transform)) = car_query.get_single_mut() {
+    for (mut camera_follow, mut camera_transform) in camera_query.iter_mut() {
+        let Some(target) = camera_follow.target else {
+            continue;
+        };
+        if let Ok((car_physics, car_transform)) = car_query.get(target) {
             camera_follow.fake_transform.translation = car_transform.translation
                 + (Vec3::new(
                     car_physics.car_transform_camera.back().x,
@@ -41,10 +62,10 @@ pub fn camera_follow(
                 camera_transform.forward(),
                 Vec3::distance(car_transform.translation,camera_transform.translation),
                 true,
-                QueryFilter::only_fixed(),
+                QueryFilter::only_fixed().exclude_sensors(),
             );
              
-            if let Some((entity, ray_intersection)) = hit {
+            if let Some((_entity, ray_intersection)) = hit {
                 camera_transform.translation = ray_intersection.point;
             }
             
@@ -1,152 +1,181 @@
 use bevy::prelude::*;
 use bevy_rapier3d::prelude::*;
 
-use crate::{car_suspension::CarPhysics, car_camera::CameraFollow, timer_text::Completion};
+use serde::Deserialize;
+
+use crate::{car_drivetrain::Drivetrain, car_suspension::{Axle, CarPhysics}, vector_operations::move_towards_f32, vehicle_input::VehicleInput};
+
+/// Below this speed the car counts as stopped, so the automatic gearbox may change direction.
+const STOPPED_SPEED: f32 = 0.5;
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct BrakeTuning {
+    /// Brake torque of the whole car with the pedal fully pressed.
+    pub max_torque: f32,
+    /// Share of `max_torque` going to the front axle.
+    pub front_bias: f32,
+}
+impl Default for BrakeTuning {
+    fn default() -> Self {
+        Self {
+            max_torque: 6.,
+            front_bias: 0.65,
+        }
+    }
+}
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct SteeringTuning {
+    /// Steering angle of the front wheels at full lock, in radians.
+    pub max_angle: f32,
+    /// How fast the wheels turn towards the requested angle, in radians per second.
+    pub speed: f32,
+    /// Speed at which only half of `max_angle` is available, so the car stays
+    /// stable at high speed.
+    pub reduction_speed: f32,
+}
+impl Default for SteeringTuning {
+    fn default() -> Self {
+        Self {
+            max_angle: 0.6,
+            speed: 2.5,
+            reduction_speed: 12.,
+        }
+    }
+}
 #[derive(Component)]
 pub struct CarController {
     pub rotated_last_frame: bool,
-    pub rotate_speed: f32,
-    pub speed: f32,
-    pub center_of_mass_altered: bool,
+    pub steering: SteeringTuning,
+    /// Current angle of the front wheels, positive to the left.
+    pub steer_angle: f32,
+    pub drivetrain: Drivetrain,
+    pub brakes: BrakeTuning,
+    pub brake: f32,
+    pub handbrake: bool,
     pub rotate_to_rotation: Quat,
     pub slerp_speed: f32,
+    /// Air drag while on the ground, kept low since the tires and engine braking slow the
+    /// car down already.
     pub car_linear_damping : f32,
 }
+impl Default for CarController {
+    fn default() -> Self {
+        Self {
+            rotated_last_frame: false,
+            steering: default(),
+            steer_angle: 0.,
+            drivetrain: default(),
+            brakes: default(),
+            brake: 0.,
+            handbrake: false,
+            rotate_to_rotation: Quat::IDENTITY,
+            slerp_speed: 5.,
+            car_linear_damping : 0.05,
+        }
+    }
+}
+type CarControlsQuery<'a> = (
+    &'a mut Damping,
+    &'a mut CarController,
+    &'a mut VehicleInput,
+    &'a mut CarPhysics,
+    &'a Velocity,
+    &'a mut Transform,
+);
 pub fn car_controls(
-    mut completion : ResMut<Completion>,
-    mut commands: Commands,
-    time: Res<Time>,
-    keys: Res<Input<KeyCode>>,
-    mut car_query: Query<
-        (
-            Entity,
-            &mut Damping,
-            &mut CarController,
-            &mut CarPhysics,
-            &mut ExternalForce,
-            &mut ExternalImpulse,
-            &mut Transform,
-        ),
-    >,
+    fixed_time: Res<FixedTime>,
+    mut car_query: Query<CarControlsQuery>,
     mut transform_query: Query<&mut Transform,Without<CarPhysics>>
 ) {
-    if let Ok((
-        entity,
+    let delta_seconds = fixed_time.period.as_secs_f32();
+    for (
         mut damping,
         mut car_controller,
+        mut input,
         mut car_physics,
-        mut force,
-        mut impulse,
+        velocity,
         car_transform,
-    )) = car_query.get_single_mut()
+    ) in car_query.iter_mut()
     {
-        if keys.just_pressed(KeyCode::Space) {
-            let _rng = rand::thread_rng();
-            let new_impluse = ExternalImpulse::at_point(
-                Vec3::new(0., 2., 0.),
-                car_transform.translation,
-                car_transform.translation,
-            );
-            impulse.impulse = new_impluse.impulse;
-            //impulse.impulse = Vec3::new(0.,0.01,0.);
-            impulse.torque_impulse = Vec3::new(1., 0., 0.);
+        let forward_speed = velocity.linvel.dot(car_transform.forward());
+        car_controller.brake = 0.;
+        car_controller.handbrake = input.handbrake;
+        let drivetrain = &mut car_controller.drivetrain;
+        drivetrain.throttle = 0.;
+        if std::mem::take(&mut input.toggle_automatic) {
+            drivetrain.automatic = !drivetrain.automatic;
         }
-        if car_controller.center_of_mass_altered == false {
-            commands
-                .entity(entity)
-                .insert(AdditionalMassProperties::MassProperties(MassProperties {
-                    mass : 1.,
-                    local_center_of_mass: Vec3::new(0., -0.5, 0.),
-                    ..default()
-                }));
-
-            car_controller.center_of_mass_altered = true;
-        }
-        let mut num_on_ground = 0;
-        for i in 0..car_physics.wheel_infos.len()
-        {
-            if car_physics.wheel_infos[i].hit == true
-            {
-                num_on_ground+=1;
-                
+        let (shift_up, shift_down) = (std::mem::take(&mut input.shift_up), std::mem::take(&mut input.shift_down));
+        if !drivetrain.automatic {
+            if shift_up {
+                drivetrain.shift_up();
+            }
+            if shift_down {
+                drivetrain.shift_down();
             }
         }
+        let num_on_ground = car_physics.wheel_infos.iter().filter(|wheel| wheel.hit).count();
         if num_on_ground>1 
         {
             damping.linear_damping = car_controller.car_linear_damping;
-            if keys.pressed(KeyCode::W) {
-                completion.started = true;
-                force.force += car_transform.forward() * car_controller.speed * time.delta_seconds();
-            }
-            if keys.just_pressed(KeyCode::W) {
-                
-                force.torque += car_transform.left() * 300.;
+            let automatic = car_controller.drivetrain.automatic;
+            let gear = car_controller.drivetrain.gear;
+            // the automatic gearbox brakes to a stop before changing direction
+            if input.throttle > 0. {
+                if automatic && gear < 0 && forward_speed < -STOPPED_SPEED {
+                    car_controller.brake = input.throttle;
+                } else {
+                    if automatic && gear <= 0 {
+                        car_controller.drivetrain.shift_to(1);
+                    }
+                    car_controller.drivetrain.throttle = input.throttle;
+                }
             }
-            if keys.pressed(KeyCode::S) {
-                completion.started = true;
-                force.force -= car_transform.forward() * car_controller.speed * time.delta_seconds();
-            }
-            if keys.just_pressed(KeyCode::S) {
-                force.torque -= car_transform.left() * 300.;
+            if input.brake > 0. {
+                if !automatic || (gear >= 0 && forward_speed > STOPPED_SPEED) {
+                    car_controller.brake = car_controller.brake.max(input.brake);
+                } else {
+                    if gear >= 0 {
+                        car_controller.drivetrain.shift_to(-1);
+                    }
+                    car_controller.drivetrain.throttle = input.brake;
+                }
             }
             car_controller.rotate_to_rotation = car_transform.rotation;
 
             car_physics.car_transform_camera.rotation = Quat::slerp(
                 car_physics.car_transform_camera.rotation,
                 car_controller.rotate_to_rotation,
-                car_controller.slerp_speed * time.delta_seconds(),
+                car_controller.slerp_speed * delta_seconds,
             );
-            if keys.pressed(KeyCode::A) {
-                force.torque += car_transform.up() * time.delta_seconds() * car_controller.rotate_speed;
-            }
-            if keys.pressed(KeyCode::D) {
-                force.torque -= car_transform.up() * time.delta_seconds() * car_controller.rotate_speed;
-            }
         }
         else {
             damping.linear_damping = 0.;
         }
-        let mut one_pressed = false;
-        if keys.pressed(KeyCode::A) {
-            one_pressed = true;
-            let mut fake_transform = car_transform.clone();
-            fake_transform.rotate_y(1.);
-            if let Ok(mut wheel_transform) = transform_query.get_mut(car_physics.wheel_infos[0].entity)
-            {
-                wheel_transform.rotation = Quat::slerp(wheel_transform.rotation,fake_transform.rotation,car_physics.wheels_animation_speed*time.delta_seconds());
-            }
-            if let Ok(mut wheel_transform) = transform_query.get_mut(car_physics.wheel_infos[1].entity)
-            {
-                wheel_transform.rotation = Quat::slerp(wheel_transform.rotation,fake_transform.rotation,car_physics.wheels_animation_speed*time.delta_seconds());
-            }
-        }
-        else if keys.pressed(KeyCode::D)
-        {
-            let mut fake_transform = car_transform.clone();
-            fake_transform.rotate_y(-1.);
-            if let Ok(mut wheel_transform) = transform_query.get_mut(car_physics.wheel_infos[0].entity)
+        let steering = car_controller.steering;
+        let max_angle = steering.max_angle / (1. + forward_speed.abs() / steering.reduction_speed);
+        car_controller.steer_angle = move_towards_f32(
+            car_controller.steer_angle,
+            -input.steer * max_angle,
+            steering.speed * delta_seconds,
+        );
+        for wheel in car_physics.wheel_infos.iter_mut().filter(|wheel| wheel.axle == Axle::Front) {
+            wheel.steer_angle = car_controller.steer_angle;
+            if let Ok(mut wheel_transform) = transform_query.get_mut(wheel.entity)
             {
-                wheel_transform.rotation = Quat::slerp(wheel_transform.rotation,fake_transform.rotation,car_physics.wheels_animation_speed*time.delta_seconds());
-            }
-            if let Ok(mut wheel_transform) = transform_query.get_mut(car_physics.wheel_infos[1].entity)
-            {
-                wheel_transform.rotation = Quat::slerp(wheel_transform.rotation,fake_transform.rotation,car_physics.wheels_animation_speed*time.delta_seconds());
+                wheel_transform.rotation = car_transform.rotation * Quat::from_rotation_y(wheel.steer_angle);
             }
         }
-        else {
-            if let Ok(mut wheel_transform) = transform_query.get_mut(car_physics.wheel_infos[0].entity)
-            {
-                wheel_transform.rotation = Quat::slerp(wheel_transform.rotation,car_transform.rotation,car_physics.wheels_stationary_animation_speed*time.delta_seconds());
-            }
-            if let Ok(mut wheel_transform) = transform_query.get_mut(car_physics.wheel_infos[1].entity)
-            {
-                wheel_transform.rotation = Quat::slerp(wheel_transform.rotation,car_transform.rotation,car_physics.wheels_stationary_animation_speed*time.delta_seconds());
-            }
+        let brakes = car_controller.brakes;
+        for wheel in car_physics.wheel_infos.iter_mut() {
+            let bias = match wheel.axle {
+                Axle::Front => brakes.front_bias,
+                Axle::Rear => 1. - brakes.front_bias,
+            };
+            // each axle has two wheels sharing its part of the brake torque
+            wheel.brake_torque = car_controller.brake * brakes.max_torque * bias / 2.;
+            wheel.locked = car_controller.handbrake && wheel.axle == Axle::Rear;
         }
         car_physics.car_transform_camera.translation = car_transform.translation;
-        if Vec3::distance(car_transform.translation,Vec3::new(-850.,118.,364.) )<=100.
-        {
-            completion.finished = true;
-        }   
     }
 }
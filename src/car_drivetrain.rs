@@ -0,0 +1,213 @@
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::{car_controls::CarController, car_suspension::{Axle, CarPhysics}, vector_operations::move_towards_f32};
+
+const RADIANS_PER_SECOND_TO_RPM: f32 = 60. / std::f32::consts::TAU;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+pub enum DriveLayout {
+    FrontWheelDrive,
+    RearWheelDrive,
+    AllWheelDrive,
+}
+impl DriveLayout {
+    pub fn drives(&self, axle: Axle) -> bool {
+        match self {
+            DriveLayout::FrontWheelDrive => axle == Axle::Front,
+            DriveLayout::RearWheelDrive => axle == Axle::Rear,
+            DriveLayout::AllWheelDrive => true,
+        }
+    }
+}
+#[derive(Clone, Debug, Deserialize)]
+pub struct EngineTuning {
+    /// `(rpm, torque)` points sorted by rpm, linearly interpolated in between.
+    pub torque_curve: Vec<(f32, f32)>,
+    pub idle_rpm: f32,
+    /// No torque is produced above this rpm.
+    pub redline_rpm: f32,
+    /// Drag torque at redline when the throttle is released.
+    pub engine_braking: f32,
+    /// How fast the revs change while the engine is not connected to the wheels.
+    pub free_rev_rate: f32,
+}
+impl EngineTuning {
+    pub fn torque(&self, rpm: f32) -> f32 {
+        let Some(&(first_rpm, first_torque)) = self.torque_curve.first() else {
+            return 0.;
+        };
+        if rpm <= first_rpm {
+            return first_torque;
+        }
+        for pair in self.torque_curve.windows(2) {
+            let ((low_rpm, low_torque), (high_rpm, high_torque)) = (pair[0], pair[1]);
+            if rpm <= high_rpm {
+                let t = (rpm - low_rpm) / (high_rpm - low_rpm).max(f32::EPSILON);
+                return low_torque + (high_torque - low_torque) * t;
+            }
+        }
+        self.torque_curve.last().map_or(0., |&(_, torque)| torque)
+    }
+}
+#[derive(Clone, Debug, Deserialize)]
+pub struct GearboxTuning {
+    pub forward_ratios: Vec<f32>,
+    pub reverse_ratio: f32,
+    pub final_drive: f32,
+    /// Seconds without drive while changing gear.
+    pub shift_time: f32,
+    /// Revs at which the automatic gearbox shifts up and down.
+    pub upshift_rpm: f32,
+    pub downshift_rpm: f32,
+}
+#[derive(Clone, Debug, Deserialize)]
+pub struct DrivetrainTuning {
+    pub engine: EngineTuning,
+    pub gearbox: GearboxTuning,
+    pub layout: DriveLayout,
+}
+impl Default for DrivetrainTuning {
+    fn default() -> Self {
+        Self {
+            engine: EngineTuning {
+                torque_curve: vec![(1000., 0.25), (4500., 0.4), (7000., 0.33)],
+                idle_rpm: 1000.,
+                redline_rpm: 7000.,
+                engine_braking: 0.08,
+                free_rev_rate: 8000.,
+            },
+            gearbox: GearboxTuning {
+                forward_ratios: vec![2.9, 2.0, 1.5, 1.15, 0.9],
+                reverse_ratio: 3.,
+                final_drive: 3.4,
+                shift_time: 0.2,
+                upshift_rpm: 6500.,
+                downshift_rpm: 3000.,
+            },
+            layout: DriveLayout::RearWheelDrive,
+        }
+    }
+}
+/// Engine and gearbox state of a car, driven by `CarController`.
+#[derive(Clone, Debug)]
+pub struct Drivetrain {
+    pub tuning: DrivetrainTuning,
+    pub rpm: f32,
+    /// `-1` is reverse, `0` neutral and `1` onwards the forward gears.
+    pub gear: i32,
+    pub automatic: bool,
+    pub throttle: f32,
+    pub shift_timer: f32,
+}
+impl Default for Drivetrain {
+    fn default() -> Self {
+        let tuning = DrivetrainTuning::default();
+        Self {
+            rpm: tuning.engine.idle_rpm,
+            tuning,
+            gear: 1,
+            automatic: true,
+            throttle: 0.,
+            shift_timer: 0.,
+        }
+    }
+}
+impl Drivetrain {
+    pub fn top_gear(&self) -> i32 {
+        self.tuning.gearbox.forward_ratios.len() as i32
+    }
+    /// Ratio between engine and wheel speed in the current gear, negative in reverse.
+    pub fn ratio(&self) -> f32 {
+        let gearbox = &self.tuning.gearbox;
+        let gear_ratio = match self.gear {
+            0 => 0.,
+            gear if gear < 0 => -gearbox.reverse_ratio,
+            // a gear the gearbox doesn't have drives nothing, like neutral
+            gear => gearbox.forward_ratios.get(gear as usize - 1).copied().unwrap_or(0.),
+        };
+        gear_ratio * gearbox.final_drive
+    }
+    pub fn shift_to(&mut self, gear: i32) {
+        let gear = gear.clamp(-1, self.top_gear());
+        if gear != self.gear {
+            self.gear = gear;
+            self.shift_timer = self.tuning.gearbox.shift_time;
+        }
+    }
+    pub fn shift_up(&mut self) {
+        self.shift_to(self.gear + 1);
+    }
+    pub fn shift_down(&mut self) {
+        self.shift_to(self.gear - 1);
+    }
+}
+/// Turns the throttle into engine torque and splits it over the driven wheels that
+/// are touching the ground.
+pub fn update_drivetrain(
+    fixed_time: Res<FixedTime>,
+    mut car_query: Query<(&mut CarController, &mut CarPhysics)>,
+) {
+    let delta_seconds = fixed_time.period.as_secs_f32();
+    for (mut car_controller, mut car_physics) in car_query.iter_mut() {
+        // pulling the handbrake dips the clutch so the locked wheels don't stall the revs
+        let clutch_pressed = car_controller.handbrake;
+        let drivetrain = &mut car_controller.drivetrain;
+        drivetrain.shift_timer = (drivetrain.shift_timer - delta_seconds).max(0.);
+        let engine = &drivetrain.tuning.engine;
+        let layout = drivetrain.tuning.layout;
+        let ratio = drivetrain.ratio();
+
+        let driven_spins: Vec<f32> = car_physics
+            .wheel_infos
+            .iter()
+            .filter(|wheel| layout.drives(wheel.axle))
+            .map(|wheel| wheel.spin)
+            .collect();
+        let grounded_driven = car_physics
+            .wheel_infos
+            .iter()
+            .filter(|wheel| wheel.hit && layout.drives(wheel.axle))
+            .count();
+        let clutch_engaged = ratio != 0. && drivetrain.shift_timer <= 0. && grounded_driven > 0 && !clutch_pressed;
+
+        if clutch_engaged {
+            // the clutch slips below idle so the car can pull away
+            let wheel_spin = driven_spins.iter().sum::<f32>() / driven_spins.len().max(1) as f32;
+            drivetrain.rpm = (wheel_spin * ratio * RADIANS_PER_SECOND_TO_RPM).max(engine.idle_rpm);
+        } else {
+            let target_rpm = engine.idle_rpm + drivetrain.throttle * (engine.redline_rpm - engine.idle_rpm);
+            drivetrain.rpm = move_towards_f32(drivetrain.rpm, target_rpm, engine.free_rev_rate * delta_seconds);
+        }
+
+        let engine_torque = if drivetrain.rpm >= engine.redline_rpm {
+            0.
+        } else if drivetrain.throttle > 0. {
+            engine.torque(drivetrain.rpm) * drivetrain.throttle
+        } else {
+            -engine.engine_braking * (drivetrain.rpm - engine.idle_rpm) / (engine.redline_rpm - engine.idle_rpm)
+        };
+
+        if drivetrain.automatic && drivetrain.gear > 0 && drivetrain.shift_timer <= 0. {
+            if drivetrain.rpm > drivetrain.tuning.gearbox.upshift_rpm && drivetrain.gear < drivetrain.top_gear() {
+                drivetrain.shift_up();
+            } else if drivetrain.rpm < drivetrain.tuning.gearbox.downshift_rpm && drivetrain.gear > 1 {
+                drivetrain.shift_down();
+            }
+        }
+
+        // open differential, every grounded driven wheel gets the same share
+        let wheel_torque = if clutch_engaged {
+            engine_torque * ratio / grounded_driven as f32
+        } else {
+            0.
+        };
+        for wheel in car_physics.wheel_infos.iter_mut() {
+            wheel.drive_torque = if wheel.hit && layout.drives(wheel.axle) {
+                wheel_torque
+            } else {
+                0.
+            };
+        }
+    }
+}
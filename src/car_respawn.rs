@@ -0,0 +1,125 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use crate::{
+    car_controls::CarController,
+    car_suspension::CarPhysics,
+    checkpoints::CheckpointPassed,
+    timer_text::Completion,
+    vehicle_input::{HumanDriver, VehicleInput},
+};
+
+/// Height above the saved pose the car is dropped from, so the wheels never start in the ground.
+const RESPAWN_LIFT: f32 = 0.5;
+/// Cars leaning further than this from upright don't count as grounded.
+const MIN_UPRIGHT: f32 = 0.7;
+
+/// When cars are put back on the track without the driver asking for it.
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct RespawnSettings {
+    /// Cars falling below this height are reset.
+    pub kill_height: f32,
+    /// Cars lying on their roof this long are reset, in seconds.
+    pub upside_down_time: f32,
+}
+impl Default for RespawnSettings {
+    fn default() -> Self {
+        Self {
+            kill_height: -100.,
+            upside_down_time: 3.,
+        }
+    }
+}
+/// Where a car is put back when it is reset.
+#[derive(Component, Clone, Debug)]
+pub struct CarRespawn {
+    /// Last pose with all four wheels on the ground and the body upright.
+    pub grounded: Transform,
+    /// Grounded pose at the last checkpoint passed, preferred over `grounded`.
+    pub checkpoint: Option<Transform>,
+    /// How long the car has been upside down, in seconds.
+    pub upside_down: f32,
+}
+impl CarRespawn {
+    pub fn new(transform: Transform) -> Self {
+        Self {
+            grounded: transform,
+            checkpoint: None,
+            upside_down: 0.,
+        }
+    }
+    /// Level pose facing the way the car was heading at the respawn point.
+    pub fn respawn_transform(&self) -> Transform {
+        let point = self.checkpoint.unwrap_or(self.grounded);
+        let heading = Vec3::new(point.forward().x, 0., point.forward().z).normalize_or_zero();
+        let heading = if heading == Vec3::ZERO { Vec3::NEG_Z } else { heading };
+        Transform::from_translation(point.translation + Vec3::Y * RESPAWN_LIFT).looking_to(heading, Vec3::Y)
+    }
+}
+/// Remembers where each car was last grounded.
+pub fn track_grounded_poses(mut car_query: Query<(&mut CarRespawn, &CarPhysics, &Transform)>) {
+    for (mut respawn, car_physics, transform) in car_query.iter_mut() {
+        if car_physics.wheel_infos.iter().all(|wheel| wheel.hit) && transform.up().y > MIN_UPRIGHT {
+            respawn.grounded = *transform;
+        }
+    }
+}
+/// Remembers where each car was grounded when it passed a checkpoint.
+pub fn track_checkpoint_respawns(
+    mut checkpoint_passed: EventReader<CheckpointPassed>,
+    mut car_query: Query<&mut CarRespawn>,
+) {
+    for event in checkpoint_passed.iter() {
+        if let Ok(mut respawn) = car_query.get_mut(event.car) {
+            respawn.checkpoint = Some(respawn.grounded);
+        }
+    }
+}
+type RespawnQuery<'a> = (
+    &'a mut CarRespawn,
+    &'a mut VehicleInput,
+    &'a mut Transform,
+    &'a mut Velocity,
+    &'a mut CarPhysics,
+    &'a mut CarController,
+    Option<&'a HumanDriver>,
+);
+/// Puts cars back at their respawn point when the driver resets, when they have been on
+/// their roof for too long, or when they fell off the world. Teleporting the player's car
+/// during a race means its time can't go on the leaderboard.
+pub fn respawn_cars(
+    fixed_time: Res<FixedTime>,
+    settings: Res<RespawnSettings>,
+    mut completion: Option<ResMut<Completion>>,
+    mut car_query: Query<RespawnQuery>,
+) {
+    let delta_seconds = fixed_time.period.as_secs_f32();
+    for (mut respawn, mut input, mut transform, mut velocity, mut car_physics, mut car_controller, human) in
+        car_query.iter_mut()
+    {
+        if transform.up().y < 0. {
+            respawn.upside_down += delta_seconds;
+        } else {
+            respawn.upside_down = 0.;
+        }
+        let reset = std::mem::take(&mut input.reset);
+        let stuck = respawn.upside_down >= settings.upside_down_time;
+        let fell = transform.translation.y < settings.kill_height;
+        if !(reset || stuck || fell) {
+            continue;
+        }
+        if let Some(completion) = completion.as_mut().filter(|completion| human.is_some() && completion.started) {
+            completion.interrupted = true;
+        }
+        *transform = respawn.respawn_transform();
+        *velocity = Velocity::zero();
+        respawn.upside_down = 0.;
+        car_physics.car_transform_camera = *transform;
+        for wheel in car_physics.wheel_infos.iter_mut() {
+            wheel.spin = 0.;
+            wheel.hit = false;
+        }
+        let drivetrain = &mut car_controller.drivetrain;
+        drivetrain.rpm = drivetrain.tuning.engine.idle_rpm;
+    }
+}
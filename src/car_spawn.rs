@@ -0,0 +1,128 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    car_controls::CarController,
+    car_respawn::CarRespawn,
+    car_suspension::{Axle, CarPhysics, WheelInfo},
+    checkpoints::RaceProgress,
+    vehicle_input::VehicleInput,
+};
+
+/// Everything needed to build a car, so one can be spawned from gameplay code at any time.
+/// Kept on the car body, so the car can be told apart and spawned again.
+#[derive(Component, Clone, Debug, Serialize, Deserialize)]
+pub struct CarSpec {
+    /// Shown on the leaderboard, which keeps separate times for each car.
+    #[serde(default)]
+    pub name: String,
+    pub body_model: String,
+    pub wheel_model: String,
+    /// Half extents of the body collider, the wheels sit at its bottom corners.
+    pub size: Vec3,
+    /// Mass added on top of the collider's own mass.
+    pub mass: f32,
+    /// Center of mass relative to the body, kept low so the car doesn't roll over.
+    pub center_of_mass: Vec3,
+    pub tuning: String,
+}
+impl Default for CarSpec {
+    fn default() -> Self {
+        Self {
+            name: "Car".to_string(),
+            body_model: "car.glb#Scene0".to_string(),
+            wheel_model: "wheel.glb#Scene0".to_string(),
+            size: Vec3::new(0.5, 0.3, 0.935),
+            mass: 1.,
+            center_of_mass: Vec3::new(0., -0.5, 0.),
+            tuning: "car.tuning.ron".to_string(),
+        }
+    }
+}
+/// Components of a car body. The wheels are separate entities listed in `car_physics`.
+#[derive(Bundle)]
+pub struct CarBundle {
+    pub spec: CarSpec,
+    pub scene: SceneBundle,
+    pub rigid_body: RigidBody,
+    pub collider: Collider,
+    pub mass: AdditionalMassProperties,
+    pub car_physics: CarPhysics,
+    pub car_controller: CarController,
+    pub input: VehicleInput,
+    pub velocity: Velocity,
+    pub impulse: ExternalImpulse,
+    pub force: ExternalForce,
+    pub gravity_scale: GravityScale,
+    pub damping: Damping,
+    pub ccd: Ccd,
+    pub active_events: ActiveEvents,
+    pub race_progress: RaceProgress,
+    pub respawn: CarRespawn,
+}
+impl CarBundle {
+    pub fn new(spec: &CarSpec, wheel_infos: Vec<WheelInfo>, transform: Transform, asset_server: &AssetServer) -> Self {
+        Self {
+            spec: spec.clone(),
+            scene: SceneBundle {
+                transform,
+                scene: asset_server.load(spec.body_model.as_str()),
+                ..default()
+            },
+            rigid_body: RigidBody::Dynamic,
+            collider: Collider::cuboid(spec.size.x, spec.size.y, spec.size.z),
+            mass: AdditionalMassProperties::MassProperties(MassProperties {
+                mass: spec.mass,
+                local_center_of_mass: spec.center_of_mass,
+                ..default()
+            }),
+            car_physics: CarPhysics {
+                wheels_stationary_animation_speed: 10.,
+                wheel_infos,
+                plane: Vec3::ZERO,
+                car_size: spec.size,
+                car_transform_camera: transform,
+                tuning: asset_server.load(spec.tuning.as_str()),
+            },
+            car_controller: CarController::default(),
+            input: VehicleInput::default(),
+            velocity: Velocity::default(),
+            impulse: ExternalImpulse::default(),
+            force: ExternalForce::default(),
+            gravity_scale: GravityScale(1.),
+            damping: Damping {
+                linear_damping: 0.,
+                angular_damping: 3.,
+            },
+            ccd: Ccd::enabled(),
+            active_events: ActiveEvents::COLLISION_EVENTS,
+            race_progress: RaceProgress::default(),
+            respawn: CarRespawn::new(transform),
+        }
+    }
+}
+/// Spawns a car body and its four wheels from `spec`, returning the body entity.
+pub fn spawn_car(commands: &mut Commands, asset_server: &AssetServer, spec: &CarSpec, transform: Transform) -> Entity {
+    let wheel_infos = [Axle::Front, Axle::Front, Axle::Rear, Axle::Rear]
+        .into_iter()
+        .map(|axle| {
+            let wheel_entity = commands
+                .spawn(SceneBundle {
+                    transform,
+                    scene: asset_server.load(spec.wheel_model.as_str()),
+                    ..default()
+                })
+                .id();
+            WheelInfo::new(wheel_entity, axle)
+        })
+        .collect();
+    commands.spawn(CarBundle::new(spec, wheel_infos, transform, asset_server)).id()
+}
+/// Despawns a car body along with its wheels, which are not children of the body.
+pub fn despawn_car(commands: &mut Commands, entity: Entity, car_physics: &CarPhysics) {
+    for wheel in car_physics.wheel_infos.iter() {
+        commands.entity(wheel.entity).despawn_recursive();
+    }
+    commands.entity(entity).despawn_recursive();
+}
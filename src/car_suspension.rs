@@ -1,12 +1,67 @@
 use bevy::prelude::*;
 use bevy_rapier3d::prelude::*;
 
-use crate::car_camera::CameraFollow;
+use crate::{car_tires::TireTuning, car_tuning::{CarTuning, SuspensionTuning}, surface::{surface_at, Surface, TriangleSurfaces}};
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Axle
+{
+    Front,
+    Rear,
+}
 #[derive(Clone)]
 pub struct WheelInfo
 {
     pub hit : bool,
     pub entity : Entity,
+    pub axle : Axle,
+    pub suspension : SuspensionTuning,
+    /// Suspension length measured by last frame's ray, used to damp the compression rate.
+    pub suspension_length : f32,
+    pub tire : TireTuning,
+    pub contact_point : Vec3,
+    pub contact_normal : Vec3,
+    /// Ground under the wheel, kept from the last contact while in the air.
+    pub surface : Surface,
+    /// Force the suspension pushes the tire into the ground with.
+    pub load : f32,
+    /// Angular velocity of the wheel around its axle, in radians per second.
+    pub spin : f32,
+    /// Torque from the drivetrain turning the wheel forwards.
+    pub drive_torque : f32,
+    /// Torque from the brakes slowing the wheel down, whichever way it spins.
+    pub brake_torque : f32,
+    /// Held still by the handbrake, whatever the engine and the road do to it.
+    pub locked : bool,
+    /// Angle the wheel is steered by around the car's up axis, positive to the left.
+    pub steer_angle : f32,
+    pub slip_ratio : f32,
+    pub slip_angle : f32,
+}
+impl WheelInfo
+{
+    pub fn new(entity : Entity, axle : Axle) -> Self
+    {
+        let suspension = SuspensionTuning::default();
+        Self {
+            hit : false,
+            entity,
+            axle,
+            suspension,
+            suspension_length : suspension.rest_length,
+            tire : TireTuning::default(),
+            contact_point : Vec3::ZERO,
+            contact_normal : Vec3::Y,
+            surface : Surface::default(),
+            load : 0.,
+            spin : 0.,
+            drive_torque : 0.,
+            brake_torque : 0.,
+            locked : false,
+            steer_angle : 0.,
+            slip_ratio : 0.,
+            slip_angle : 0.,
+        }
+    }
 }
 #[derive(Component)]
 pub struct CarPhysics {
@@ -14,25 +69,25 @@ pub struct CarPhysics {
     pub car_size : Vec3,
     pub wheel_infos : Vec<WheelInfo>,
     pub car_transform_camera: Transform,
-    pub wheels_animation_speed : f32,
     pub wheels_stationary_animation_speed : f32,
+    pub tuning : Handle<CarTuning>,
 }
 pub fn update_car_suspension(
-    time: Res<Time>,
-    _commands: Commands,
+    fixed_time: Res<FixedTime>,
     rapier_context: Res<RapierContext>,
     mut car_query: Query<
         (
             &mut CarPhysics,
             &mut ExternalForce,
-            &mut Velocity,
             &mut Transform,
         ),
     >,
-    mut transform_query: Query<&mut Transform,Without<CarPhysics>>
+    mut transform_query: Query<&mut Transform,Without<CarPhysics>>,
+    surface_query: Query<(Option<&Surface>, Option<&TriangleSurfaces>)>,
 ) {
-    if let Ok((mut car_physics, mut force, velocity, car_transform)) = car_query.get_single_mut() {
-        
+    let delta_seconds = fixed_time.period.as_secs_f32();
+    for (mut car_physics, mut force, car_transform) in car_query.iter_mut() {
+
         let f_r_d = car_transform.translation
             + (car_transform.down() * car_physics.car_size.y + car_transform.forward() * car_physics.car_size.z)
             + (car_transform.right() * car_physics.car_size.x);
@@ -46,60 +101,67 @@ pub fn update_car_suspension(
             + (car_transform.down() * car_physics.car_size.y + car_transform.back() * car_physics.car_size.z)
             + (car_transform.left() * car_physics.car_size.x);
 
-        let mut wheel_vec: Vec<Vec3> = Vec::new();
-        wheel_vec.push(f_r_d);
-        wheel_vec.push(f_l_d);
-        wheel_vec.push(b_r_d);
-        wheel_vec.push(b_l_d);
+        let wheel_vec: Vec<Vec3> = vec![f_r_d, f_l_d, b_r_d, b_l_d];
 
-        let max_suspension = 0.1;
         force.force = Vec3::ZERO;
         force.torque = Vec3::ZERO;
-        for i in 0..wheel_vec.len() {
+        for (i, wheel_position) in wheel_vec.iter().enumerate() {
+            let suspension = car_physics.wheel_infos[i].suspension;
+            let wheel_radius = car_physics.wheel_infos[i].tire.radius;
             if let Ok(mut wheel_transform) = transform_query.get_mut(car_physics.wheel_infos[i].entity)
             {
                 let hit = rapier_context.cast_ray_and_get_normal(
-                    wheel_vec[i]+car_transform.up()*0.01,
+                    *wheel_position+car_transform.up()*0.01,
                     car_transform.down(),
-                    max_suspension,
+                    suspension.rest_length,
                     true,
-                    QueryFilter::only_fixed(),
+                    QueryFilter::only_fixed().exclude_sensors(),
                 );
-                if let Some((_entity, ray_intersection)) = hit {
-                    car_physics.wheel_infos[i].hit = true;
-                    let compression = 1. - (ray_intersection.toi * car_transform.down().length() / max_suspension);
-                    let suspension_strength = 15000.;
-                    let suspension_damping = 1200.;
+                if let Some((ground, ray_intersection)) = hit {
+                    let suspension_length = ray_intersection.toi * car_transform.down().length();
+                    let compression = (suspension.rest_length - suspension_length) / suspension.travel;
+                    // positive while the wheel is being pushed up into the body
+                    let compression_speed = (car_physics.wheel_infos[i].suspension_length - suspension_length) / delta_seconds;
+                    let suspension_force = (compression * suspension.spring_rate)
+                        + (suspension.damper_rate * compression_speed);
+                    let wheel_info = &mut car_physics.wheel_infos[i];
+                    wheel_info.hit = true;
+                    wheel_info.suspension_length = suspension_length;
+                    wheel_info.contact_point = ray_intersection.point;
+                    wheel_info.contact_normal = ray_intersection.normal;
+                    let (surface, triangle_surfaces) = surface_query.get(ground).unwrap_or_default();
+                    wheel_info.surface = surface_at(surface, triangle_surfaces, ray_intersection.feature);
+                    wheel_info.load = suspension_force.max(0.);
 
                     let add_force = ExternalForce::at_point(
-                        car_transform.up()
-                            * ((compression * suspension_strength)
-                                - (suspension_damping * (velocity.linvel.y)))
-                            * time.delta_seconds(),
-                        wheel_vec[i],
+                        car_transform.up() * suspension_force,
+                        *wheel_position,
                         car_transform.translation,
                     );
 
                     force.force += add_force.force;
                     force.torque += add_force.torque;
 
-                    wheel_transform.translation = ray_intersection.point+car_transform.up()*0.2;
+                    wheel_transform.translation = ray_intersection.point+car_transform.up()*wheel_radius;
                     if i == 2 || i == 3
                     {
-                        wheel_transform.rotation = Quat::slerp(wheel_transform.rotation,car_transform.rotation,car_physics.wheels_stationary_animation_speed*time.delta_seconds());
+                        wheel_transform.rotation = Quat::slerp(wheel_transform.rotation,car_transform.rotation,car_physics.wheels_stationary_animation_speed*delta_seconds);
                     }
                 }
                 else
                 {
-                    car_physics.wheel_infos[i].hit = false;
-                    
-                    wheel_transform.translation = wheel_vec[i]-car_transform.up()*(max_suspension-0.2);
+                    let wheel_info = &mut car_physics.wheel_infos[i];
+                    wheel_info.hit = false;
+                    wheel_info.suspension_length = suspension.rest_length;
+                    wheel_info.load = 0.;
+
+                    wheel_transform.translation = *wheel_position-car_transform.up()*(suspension.rest_length-wheel_radius);
                     if i == 2 || i == 3
                     {
-                        wheel_transform.rotation = Quat::slerp(wheel_transform.rotation,car_transform.rotation,car_physics.wheels_stationary_animation_speed*time.delta_seconds());
+                        wheel_transform.rotation = Quat::slerp(wheel_transform.rotation,car_transform.rotation,car_physics.wheels_stationary_animation_speed*delta_seconds);
                     }
-                   
-                    
+
+
                 }
             }
         }
@@ -0,0 +1,163 @@
+use bevy::prelude::*;
+use bevy_rapier3d::{prelude::*, rapier::{dynamics::RigidBody as RapierRigidBody, math::Vector}};
+use serde::Deserialize;
+
+use crate::{car_suspension::CarPhysics, surface::SurfaceTable, vector_operations::move_towards_f32};
+
+/// Below this speed slip is measured against a constant instead of the wheel speed,
+/// otherwise the slip values blow up when the car is nearly stopped.
+const LOW_SPEED: f32 = 1.;
+
+/// Simplified Pacejka "magic formula" curve mapping slip to a friction coefficient.
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct TireCurve {
+    /// B, how quickly the force builds up with slip.
+    pub stiffness: f32,
+    /// C, the overall shape of the curve.
+    pub shape: f32,
+    /// D, the friction coefficient at the peak of the curve.
+    pub peak: f32,
+    /// E, how much grip is lost past the peak.
+    pub curvature: f32,
+}
+impl TireCurve {
+    pub fn evaluate(&self, slip: f32) -> f32 {
+        let x = self.stiffness * slip;
+        self.peak * (self.shape * (x - self.curvature * (x - x.atan())).atan()).sin()
+    }
+}
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct TireTuning {
+    pub radius: f32,
+    pub inertia: f32,
+    pub rolling_resistance: f32,
+    /// Force along the wheel from the slip ratio.
+    pub longitudinal: TireCurve,
+    /// Force across the wheel from the slip angle in radians.
+    pub lateral: TireCurve,
+}
+impl Default for TireTuning {
+    fn default() -> Self {
+        Self {
+            radius: 0.2,
+            inertia: 0.02,
+            rolling_resistance: 0.015,
+            longitudinal: TireCurve {
+                stiffness: 10.,
+                shape: 1.65,
+                peak: 1.,
+                curvature: 0.97,
+            },
+            lateral: TireCurve {
+                stiffness: 10.,
+                shape: 1.3,
+                peak: 1.,
+                curvature: 0.97,
+            },
+        }
+    }
+}
+/// Applies the longitudinal and lateral tire forces at every wheel contact found by
+/// `update_car_suspension`, and spins the wheels along with the ground. The surface under
+/// each wheel scales its grip and adds to its rolling resistance.
+pub fn update_car_tires(
+    fixed_time: Res<FixedTime>,
+    rapier_context: Res<RapierContext>,
+    surfaces: Res<SurfaceTable>,
+    mut car_query: Query<(Entity, &mut CarPhysics, &mut ExternalForce, &Velocity, &Transform)>,
+) {
+    let delta_seconds = fixed_time.period.as_secs_f32();
+    for (entity, mut car_physics, mut force, velocity, car_transform) in car_query.iter_mut() {
+        let Some(body) = rapier_context
+            .entity2body()
+            .get(&entity)
+            .and_then(|handle| rapier_context.bodies.get(*handle))
+        else {
+            continue;
+        };
+        let grounded = car_physics.wheel_infos.iter().filter(|wheel| wheel.hit).count().max(1) as f32;
+        for wheel in car_physics.wheel_infos.iter_mut() {
+            let tire = wheel.tire;
+            wheel.spin = move_towards_f32(wheel.spin, 0., wheel.brake_torque / tire.inertia * delta_seconds);
+            if wheel.locked {
+                wheel.spin = 0.;
+            }
+            if !wheel.hit || wheel.load <= 0. {
+                wheel.slip_ratio = 0.;
+                wheel.slip_angle = 0.;
+                continue;
+            }
+            let ground = surfaces.get(wheel.surface);
+            let normal = wheel.contact_normal;
+            let heading = Quat::from_axis_angle(car_transform.up(), wheel.steer_angle) * car_transform.forward();
+            let forward = (heading - normal * heading.dot(normal)).normalize_or_zero();
+            let side = forward.cross(normal);
+
+            let point_velocity = velocity.linvel
+                + velocity.angvel.cross(wheel.contact_point - car_transform.translation);
+            let forward_speed = point_velocity.dot(forward);
+            let side_speed = point_velocity.dot(side);
+            let reference_speed = forward_speed.abs().max(LOW_SPEED);
+            let spin_speed = wheel.spin * tire.radius;
+
+            wheel.slip_ratio = (spin_speed - forward_speed) / reference_speed;
+            wheel.slip_angle = side_speed.atan2(reference_speed);
+
+            let mut longitudinal = tire.longitudinal.evaluate(wheel.slip_ratio) * ground.grip * wheel.load;
+            let mut lateral = -tire.lateral.evaluate(wheel.slip_angle) * ground.grip * wheel.load;
+
+            // both directions share the same patch of rubber
+            let max_grip = tire.longitudinal.peak.max(tire.lateral.peak) * ground.grip * wheel.load;
+            let combined = Vec2::new(longitudinal, lateral).length();
+            if combined > max_grip {
+                longitudinal *= max_grip / combined;
+                lateral *= max_grip / combined;
+            }
+
+            // never push harder than it takes to cancel the slip in one step, shared
+            // between all the wheels on the ground
+            let max_longitudinal = effective_mass(body, wheel.contact_point, forward) / grounded
+                * (spin_speed - forward_speed).abs() / delta_seconds;
+            longitudinal = longitudinal.clamp(-max_longitudinal, max_longitudinal);
+            let max_lateral = effective_mass(body, wheel.contact_point, side) / grounded
+                * side_speed.abs() / delta_seconds;
+            lateral = lateral.clamp(-max_lateral, max_lateral);
+
+            if !wheel.locked {
+                wheel.spin += wheel.drive_torque / tire.inertia * delta_seconds;
+                // the road pulls the wheel towards rolling speed, without overshooting it
+                let rolling_spin = forward_speed / tire.radius;
+                let spin_change = -longitudinal * tire.radius / tire.inertia * delta_seconds;
+                if spin_change.abs() >= (rolling_spin - wheel.spin).abs() {
+                    wheel.spin = rolling_spin;
+                } else {
+                    wheel.spin += spin_change;
+                }
+            }
+
+            let rolling_resistance =
+                (tire.rolling_resistance + ground.rolling_resistance) * wheel.load * forward_speed.clamp(-1., 1.);
+            let add_force = ExternalForce::at_point(
+                forward * (longitudinal - rolling_resistance) + side * lateral,
+                wheel.contact_point,
+                car_transform.translation,
+            );
+            force.force += add_force.force;
+            force.torque += add_force.torque;
+        }
+    }
+}
+/// Mass the car resists with when pushed at `point` along `direction`, lower than its
+/// actual mass because part of the push turns the car instead of moving it.
+fn effective_mass(body: &RapierRigidBody, point: Vec3, direction: Vec3) -> f32 {
+    let mass_properties = body.mass_properties();
+    let arm = point - Vec3::from(mass_properties.world_com.coords);
+    let torque_arm: Vector<f32> = arm.cross(direction).into();
+    let angular = mass_properties.effective_world_inv_inertia_sqrt * torque_arm;
+    let inverse_mass = mass_properties.effective_inv_mass.x + angular.dot(&angular);
+    if inverse_mass > 0. {
+        1. / inverse_mass
+    } else {
+        0.
+    }
+}
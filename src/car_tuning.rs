@@ -0,0 +1,122 @@
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    utils::BoxedFuture,
+};
+use serde::Deserialize;
+
+use crate::{car_controls::{BrakeTuning, CarController, SteeringTuning}, car_drivetrain::DrivetrainTuning, car_suspension::{Axle, CarPhysics}, car_tires::TireTuning};
+
+/// Spring and damper setup for a single wheel.
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct SuspensionTuning {
+    /// Force pushed back at full compression.
+    pub spring_rate: f32,
+    /// Force resisting the wheel per meter per second it moves into or out of the body.
+    pub damper_rate: f32,
+    /// Length of the suspension ray, the spring is relaxed at this length.
+    pub rest_length: f32,
+    /// How far the spring compresses from `rest_length` before reaching full force.
+    pub travel: f32,
+}
+impl Default for SuspensionTuning {
+    fn default() -> Self {
+        Self {
+            spring_rate: 20.,
+            damper_rate: 8.,
+            rest_length: 0.1,
+            travel: 0.1,
+        }
+    }
+}
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+pub struct AxleTuning {
+    #[serde(default)]
+    pub suspension: SuspensionTuning,
+    #[serde(default)]
+    pub tire: TireTuning,
+}
+/// Handling setup for a car, loaded from a `.tuning.ron` file.
+#[derive(Clone, Debug, Default, Deserialize, TypeUuid)]
+#[uuid = "6f0b2ad2-51f4-4a0e-9a3b-0a3c8f1e7d42"]
+pub struct CarTuning {
+    pub front: AxleTuning,
+    pub rear: AxleTuning,
+    #[serde(default)]
+    pub drivetrain: DrivetrainTuning,
+    #[serde(default)]
+    pub brakes: BrakeTuning,
+    #[serde(default)]
+    pub steering: SteeringTuning,
+}
+impl CarTuning {
+    /// Refuses setups the car can't be driven with, the loader fails on these.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.drivetrain.gearbox.forward_ratios.is_empty() {
+            return Err("the gearbox needs at least one forward gear".to_string());
+        }
+        Ok(())
+    }
+    pub fn axle(&self, axle: Axle) -> &AxleTuning {
+        match axle {
+            Axle::Front => &self.front,
+            Axle::Rear => &self.rear,
+        }
+    }
+}
+#[derive(Default)]
+pub struct CarTuningLoader;
+impl AssetLoader for CarTuningLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let tuning: CarTuning = ron::de::from_bytes(bytes)?;
+            tuning.validate().map_err(bevy::asset::Error::msg)?;
+            load_context.set_default_asset(LoadedAsset::new(tuning));
+            Ok(())
+        })
+    }
+    fn extensions(&self) -> &[&str] {
+        &["tuning.ron"]
+    }
+}
+/// Copies the tuning asset onto the wheels, drivetrain, brakes and steering of every car using it, when
+/// the car is spawned and whenever the asset is (re)loaded.
+pub fn apply_car_tuning(
+    mut events: EventReader<AssetEvent<CarTuning>>,
+    tunings: Res<Assets<CarTuning>>,
+    mut car_query: Query<(&mut CarPhysics, Option<&mut CarController>)>,
+) {
+    let changed: Vec<Handle<CarTuning>> = events
+        .iter()
+        .filter_map(|event| match event {
+            AssetEvent::Created { handle } | AssetEvent::Modified { handle } => Some(handle.clone()),
+            AssetEvent::Removed { .. } => None,
+        })
+        .collect();
+    for (mut car_physics, car_controller) in car_query.iter_mut() {
+        if !car_physics.is_added() && !changed.contains(&car_physics.tuning) {
+            continue;
+        }
+        let Some(tuning) = tunings.get(&car_physics.tuning) else {
+            continue;
+        };
+        for wheel in car_physics.wheel_infos.iter_mut() {
+            let axle = tuning.axle(wheel.axle);
+            wheel.suspension = axle.suspension;
+            wheel.tire = axle.tire;
+        }
+        if let Some(mut car_controller) = car_controller {
+            let drivetrain = &mut car_controller.drivetrain;
+            drivetrain.tuning = tuning.drivetrain.clone();
+            // the new gearbox may not have the gear the car is in
+            drivetrain.gear = drivetrain.gear.clamp(-1, drivetrain.top_gear());
+            car_controller.brakes = tuning.brakes;
+            car_controller.steering = tuning.steering;
+        }
+    }
+}
@@ -0,0 +1,206 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use serde::Deserialize;
+
+use crate::{
+    timer_text::Completion,
+    track::{active_track_changed, ActiveTrack, TrackManifest},
+    vehicle_input::HumanDriver,
+};
+
+/// Slower than this, in meters per second, a car isn't going any way at all.
+const WRONG_WAY_MIN_SPEED: f32 = 2.;
+/// Cosine of the angle between the way a car moves and the way to its next gate beyond
+/// which it is going the wrong way, about 120 degrees so tight bends don't count.
+const WRONG_WAY_COSINE: f32 = -0.5;
+
+/// Box a car has to drive through, placed and turned by `translation` and `rotation`.
+#[derive(Clone, Debug, Deserialize)]
+pub struct CheckpointSpec {
+    pub translation: Vec3,
+    #[serde(default)]
+    pub rotation: Quat,
+    pub half_extents: Vec3,
+}
+#[derive(Component)]
+pub struct Checkpoint {
+    pub index: usize,
+}
+/// How far a car has got through the checkpoints.
+#[derive(Component, Clone, Debug, Default)]
+pub struct RaceProgress {
+    pub next_checkpoint: usize,
+    /// `0` until the car crosses the start line.
+    pub lap: u32,
+    pub wrong_way: bool,
+    pub finished: bool,
+}
+pub struct CheckpointPassed {
+    pub car: Entity,
+    pub checkpoint: usize,
+    pub lap: u32,
+}
+pub struct LapCompleted {
+    pub car: Entity,
+    pub lap: u32,
+}
+pub struct RaceFinished {
+    pub car: Entity,
+}
+/// Sent when a car in the race starts moving away from the next gate it has to pass, and
+/// again with `wrong_way` false once it heads back towards it.
+pub struct WrongWay {
+    pub car: Entity,
+    pub wrong_way: bool,
+}
+/// Replaces the checkpoints in the world whenever another track is picked or its manifest
+/// is (re)loaded, and restarts every car's progress.
+pub fn spawn_checkpoints(
+    mut commands: Commands,
+    mut events: EventReader<AssetEvent<TrackManifest>>,
+    manifests: Res<Assets<TrackManifest>>,
+    active_track: Option<Res<ActiveTrack>>,
+    checkpoint_query: Query<Entity, With<Checkpoint>>,
+    mut progress_query: Query<&mut RaceProgress>,
+) {
+    let Some(active_track) = active_track else {
+        return;
+    };
+    if !active_track_changed(&mut events, &active_track) {
+        return;
+    }
+    let Some(manifest) = manifests.get(&active_track.0) else {
+        return;
+    };
+    for entity in checkpoint_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    for (index, checkpoint) in manifest.gates().enumerate() {
+        let half_extents = checkpoint.half_extents;
+        commands.spawn((
+            TransformBundle::from(Transform::from_translation(checkpoint.translation).with_rotation(checkpoint.rotation)),
+            Collider::cuboid(half_extents.x, half_extents.y, half_extents.z),
+            Sensor,
+            ActiveEvents::COLLISION_EVENTS,
+            Checkpoint { index },
+        ));
+    }
+    for mut progress in progress_query.iter_mut() {
+        *progress = RaceProgress::default();
+    }
+}
+pub fn despawn_checkpoints(mut commands: Commands, checkpoint_query: Query<Entity, With<Checkpoint>>) {
+    for entity in checkpoint_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+#[allow(clippy::too_many_arguments)]
+pub fn update_race_progress(
+    mut collision_events: EventReader<CollisionEvent>,
+    manifests: Res<Assets<TrackManifest>>,
+    active_track: Option<Res<ActiveTrack>>,
+    checkpoint_query: Query<&Checkpoint>,
+    mut progress_query: Query<&mut RaceProgress>,
+    mut checkpoint_passed: EventWriter<CheckpointPassed>,
+    mut lap_completed: EventWriter<LapCompleted>,
+    mut race_finished: EventWriter<RaceFinished>,
+) {
+    let Some(manifest) = active_track.and_then(|active_track| manifests.get(&active_track.0)) else {
+        return;
+    };
+    let count = manifest.gates().count();
+    for event in collision_events.iter() {
+        let CollisionEvent::Started(first, second, _) = *event else {
+            continue;
+        };
+        let (car, checkpoint) = match checkpoint_query.get(first) {
+            Ok(checkpoint) => (second, checkpoint),
+            Err(_) => match checkpoint_query.get(second) {
+                Ok(checkpoint) => (first, checkpoint),
+                Err(_) => continue,
+            },
+        };
+        let Ok(mut progress) = progress_query.get_mut(car) else {
+            continue;
+        };
+        if progress.finished {
+            continue;
+        }
+        let index = checkpoint.index;
+        if index != progress.next_checkpoint {
+            continue;
+        }
+        let lap_done = if index == 0 {
+            // the start line starts the first lap and ends every other one on a circuit
+            let lap_done = progress.lap > 0;
+            if !lap_done {
+                progress.lap = 1;
+            }
+            lap_done
+        } else {
+            !manifest.circuit && index == count - 1
+        };
+        checkpoint_passed.send(CheckpointPassed {
+            car,
+            checkpoint: index,
+            lap: progress.lap,
+        });
+        progress.next_checkpoint = (index + 1) % count;
+        if lap_done {
+            lap_completed.send(LapCompleted { car, lap: progress.lap });
+            if !manifest.circuit || progress.lap >= manifest.laps {
+                progress.finished = true;
+                race_finished.send(RaceFinished { car });
+            } else {
+                progress.lap += 1;
+            }
+        }
+    }
+}
+/// Compares the way each racing car moves with the way to its next gate, wherever it turned
+/// around, and flags it while it is going the wrong way.
+pub fn detect_wrong_way(
+    manifests: Res<Assets<TrackManifest>>,
+    active_track: Option<Res<ActiveTrack>>,
+    mut car_query: Query<(Entity, &mut RaceProgress, &Transform, &Velocity)>,
+    mut wrong_way: EventWriter<WrongWay>,
+) {
+    let Some(manifest) = active_track.and_then(|active_track| manifests.get(&active_track.0)) else {
+        return;
+    };
+    for (car, mut progress, transform, velocity) in car_query.iter_mut() {
+        if progress.lap == 0 || progress.finished {
+            continue;
+        }
+        let Some(gate) = manifest.gates().nth(progress.next_checkpoint) else {
+            continue;
+        };
+        let motion = Vec3::new(velocity.linvel.x, 0., velocity.linvel.z);
+        let to_gate = gate.translation - transform.translation;
+        let to_gate = Vec3::new(to_gate.x, 0., to_gate.z).normalize_or_zero();
+        if motion.length() < WRONG_WAY_MIN_SPEED || to_gate == Vec3::ZERO {
+            continue;
+        }
+        let heading = motion.normalize().dot(to_gate);
+        // only back on the right way once it heads towards the gate, so it doesn't flicker
+        // at the edge
+        let going_wrong_way = if progress.wrong_way { heading <= 0. } else { heading < WRONG_WAY_COSINE };
+        if going_wrong_way != progress.wrong_way {
+            progress.wrong_way = going_wrong_way;
+            wrong_way.send(WrongWay {
+                car,
+                wrong_way: going_wrong_way,
+            });
+        }
+    }
+}
+/// Stops the timer once the player's car finishes.
+pub fn finish_player_race(
+    mut race_finished: EventReader<RaceFinished>,
+    human_query: Query<(), With<HumanDriver>>,
+    mut completion: ResMut<Completion>,
+) {
+    if race_finished.iter().any(|event| human_query.contains(event.car)) {
+        completion.finished = true;
+    }
+}
@@ -0,0 +1,287 @@
+use std::path::{Path, PathBuf};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    car_spawn::CarSpec,
+    race_state::RaceRestarted,
+    ron_file::{self, RonFileError},
+    timer_text::Completion,
+    track::{active_track_changed, ActiveTrack, TrackManifest},
+    vehicle_input::HumanDriver,
+    VehicleSet,
+};
+
+/// Where the fastest run on each track is kept between attempts, unless `GhostFolder`
+/// says otherwise.
+pub const GHOST_FOLDER: &str = "recordings";
+const SAMPLE_INTERVAL: f32 = 0.1;
+const GHOST_ALPHA: f32 = 0.35;
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct GhostSample {
+    pub translation: Vec3,
+    pub rotation: Quat,
+}
+/// Car transforms of a whole run, sampled every `sample_interval` seconds.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GhostRun {
+    pub sample_interval: f32,
+    pub samples: Vec<GhostSample>,
+    /// Seconds from the start to the finish.
+    pub time: f32,
+    /// Body of the car that drove the run, which the ghost looks like.
+    #[serde(default = "default_body_model")]
+    pub body_model: String,
+}
+fn default_body_model() -> String {
+    CarSpec::default().body_model
+}
+impl Default for GhostRun {
+    fn default() -> Self {
+        Self {
+            sample_interval: SAMPLE_INTERVAL,
+            samples: Vec::new(),
+            time: 0.,
+            body_model: default_body_model(),
+        }
+    }
+}
+impl GhostRun {
+    /// Transform of the car `time` seconds into the run, holding the last sample once the
+    /// run is over.
+    pub fn sample(&self, time: f32) -> Option<Transform> {
+        let position = (time / self.sample_interval).max(0.);
+        let index = position as usize;
+        let from = self.samples.get(index).or(self.samples.last())?;
+        let to = self.samples.get(index + 1).unwrap_or(from);
+        let t = position.fract();
+        Some(Transform {
+            translation: from.translation.lerp(to.translation, t),
+            rotation: from.rotation.slerp(to.rotation, t),
+            ..default()
+        })
+    }
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), RonFileError> {
+        ron_file::save(self, path)
+    }
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, RonFileError> {
+        ron_file::load(path)
+    }
+}
+/// Fastest finished run so far, raced against as a ghost.
+#[derive(Resource, Default)]
+pub struct BestGhost(pub Option<GhostRun>);
+/// Run of the player's car in progress.
+#[derive(Resource, Default)]
+pub struct GhostRecording {
+    /// Name of the track raced on, which picks the file the run is saved to.
+    pub track: String,
+    pub run: GhostRun,
+    pub saved: bool,
+}
+/// Translucent copy of the car body following the best run, without any physics.
+#[derive(Component, Default)]
+pub struct GhostCar {
+    /// Body the ghost was spawned with, it is spawned again when the best run was driven
+    /// with another car.
+    pub body_model: String,
+}
+/// Records the player's runs and races them against the fastest one. Needs the renderer
+/// for the ghost's materials, so it is kept out of `VehiclePlugin`.
+pub struct GhostCarPlugin;
+impl Plugin for GhostCarPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<BestGhost>()
+            .init_resource::<GhostRecording>()
+            .init_resource::<GhostFolder>()
+            .add_system(load_best_ghost)
+            .add_system(record_ghost.after(VehicleSet::Controls).in_schedule(CoreSchedule::FixedUpdate))
+            .add_system(restart_ghost)
+            .add_systems((spawn_ghost_car, move_ghost_car, make_ghost_translucent).chain());
+    }
+}
+/// Folder the best run on each track is saved to and loaded from.
+#[derive(Resource)]
+pub struct GhostFolder(pub PathBuf);
+impl Default for GhostFolder {
+    fn default() -> Self {
+        Self(PathBuf::from(GHOST_FOLDER))
+    }
+}
+impl GhostFolder {
+    /// File holding the best run on `track`.
+    pub fn ghost_path(&self, track: &str) -> PathBuf {
+        let name: String = track
+            .chars()
+            .map(|c| if c.is_alphanumeric() { c.to_ascii_lowercase() } else { '_' })
+            .collect();
+        self.0.join(format!("{name}.ghost.ron"))
+    }
+}
+/// Swaps in the best run of the track whenever another one is picked.
+#[allow(clippy::too_many_arguments)]
+pub fn load_best_ghost(
+    mut commands: Commands,
+    mut events: EventReader<AssetEvent<TrackManifest>>,
+    manifests: Res<Assets<TrackManifest>>,
+    active_track: Option<Res<ActiveTrack>>,
+    folder: Res<GhostFolder>,
+    mut best_ghost: ResMut<BestGhost>,
+    mut recording: ResMut<GhostRecording>,
+    ghost_query: Query<Entity, With<GhostCar>>,
+) {
+    let Some(active_track) = active_track else {
+        return;
+    };
+    if !active_track_changed(&mut events, &active_track) {
+        return;
+    }
+    let Some(manifest) = manifests.get(&active_track.0) else {
+        return;
+    };
+    for entity in ghost_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    *recording = GhostRecording {
+        track: manifest.name.clone(),
+        ..default()
+    };
+    let path = folder.ghost_path(&manifest.name);
+    best_ghost.0 = None;
+    if !path.exists() {
+        return;
+    }
+    match GhostRun::load(&path) {
+        Ok(run) => best_ghost.0 = Some(run),
+        Err(err) => error!("Could not load the ghost from {}: {err}", path.display()),
+    }
+}
+/// Samples the player's car at the race clock while it runs, and keeps the run once it is
+/// the fastest to reach the finish.
+pub fn record_ghost(
+    completion: Res<Completion>,
+    folder: Res<GhostFolder>,
+    mut recording: ResMut<GhostRecording>,
+    mut best_ghost: ResMut<BestGhost>,
+    car_query: Query<(&Transform, &CarSpec), With<HumanDriver>>,
+) {
+    if !completion.started || recording.saved {
+        return;
+    }
+    if completion.finished {
+        recording.saved = true;
+        recording.run.time = completion.elapsed;
+        if best_ghost.0.as_ref().is_some_and(|best| best.time <= recording.run.time) {
+            return;
+        }
+        let path = folder.ghost_path(&recording.track);
+        if let Err(err) = recording.run.save(&path) {
+            error!("Could not save the ghost to {}: {err}", path.display());
+        }
+        best_ghost.0 = Some(recording.run.clone());
+        return;
+    }
+    let Some((car_transform, spec)) = car_query.iter().next() else {
+        return;
+    };
+    let recording = recording.as_mut();
+    if recording.run.samples.is_empty() {
+        recording.run.body_model = spec.body_model.clone();
+    }
+    if completion.elapsed >= recording.run.samples.len() as f32 * recording.run.sample_interval {
+        recording.run.samples.push(GhostSample {
+            translation: car_transform.translation,
+            rotation: car_transform.rotation,
+        });
+    }
+}
+/// Drops the unfinished run and sends the ghost back to the start.
+pub fn restart_ghost(
+    mut restarted: EventReader<RaceRestarted>,
+    mut recording: ResMut<GhostRecording>,
+    mut ghost_query: Query<&mut Visibility, With<GhostCar>>,
+) {
+    if restarted.iter().count() == 0 {
+        return;
+    }
+    *recording = GhostRecording {
+        track: std::mem::take(&mut recording.track),
+        ..default()
+    };
+    for mut visibility in ghost_query.iter_mut() {
+        *visibility = Visibility::Hidden;
+    }
+}
+/// Spawns the ghost in the body of the car that drove the best run.
+pub fn spawn_ghost_car(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    best_ghost: Res<BestGhost>,
+    ghost_query: Query<(Entity, &GhostCar)>,
+) {
+    let Some(run) = best_ghost.0.as_ref() else {
+        return;
+    };
+    let mut spawned = false;
+    for (entity, ghost) in ghost_query.iter() {
+        if ghost.body_model == run.body_model {
+            spawned = true;
+        } else {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+    if spawned {
+        return;
+    }
+    commands.spawn((
+        SceneBundle {
+            scene: asset_server.load(run.body_model.as_str()),
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+        GhostCar {
+            body_model: run.body_model.clone(),
+        },
+    ));
+}
+/// Puts the ghost where the best run was at the same time on the race clock, so it keeps
+/// pace with the player's physics steps rather than the frame rate.
+pub fn move_ghost_car(
+    completion: Res<Completion>,
+    best_ghost: Res<BestGhost>,
+    mut ghost_query: Query<(&mut Transform, &mut Visibility), With<GhostCar>>,
+) {
+    let Some(run) = best_ghost.0.as_ref() else {
+        return;
+    };
+    for (mut transform, mut visibility) in ghost_query.iter_mut() {
+        if !completion.started {
+            continue;
+        }
+        if let Some(sample) = run.sample(completion.elapsed) {
+            *transform = sample;
+            *visibility = Visibility::Inherited;
+        }
+    }
+}
+/// Swaps the materials of the ghost's scene for see-through copies once it has spawned.
+pub fn make_ghost_translucent(
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut material_query: Query<(Entity, &mut Handle<StandardMaterial>), Added<Handle<StandardMaterial>>>,
+    parent_query: Query<&Parent>,
+    ghost_query: Query<(), With<GhostCar>>,
+) {
+    for (entity, mut material) in material_query.iter_mut() {
+        if !parent_query.iter_ancestors(entity).any(|ancestor| ghost_query.contains(ancestor)) {
+            continue;
+        }
+        let Some(mut ghost_material) = materials.get(&material).cloned() else {
+            continue;
+        };
+        ghost_material.base_color.set_a(GHOST_ALPHA);
+        ghost_material.alpha_mode = AlphaMode::Blend;
+        *material = materials.add(ghost_material);
+    }
+}
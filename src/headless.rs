@@ -0,0 +1,73 @@
+use bevy::{ecs::system::CommandQueue, input::InputPlugin, prelude::*, scene::ScenePlugin, time::TimeUpdateStrategy, utils::Instant};
+use bevy_rapier3d::prelude::*;
+
+use crate::{car_spawn::{spawn_car, CarSpec}, race_state::{RacePlugin, RaceState}, VehiclePlugin};
+
+/// App with the vehicle simulation and the race but no window or renderer, for tests and
+/// CI machines without a GPU. Time only moves forward through `step_frames`, and the race
+/// is on from the start without a countdown.
+pub fn headless_app() -> App {
+    let mut app = bare_app();
+    app.add_plugin(RacePlugin)
+        .insert_resource(NextState(Some(RaceState::Racing)));
+    // the first update only starts the clock, runs the startup systems and enters the race
+    app.update();
+    app
+}
+/// Headless app with only `VehiclePlugin`, the way a game with its own flow would use it.
+pub fn headless_vehicle_app() -> App {
+    let mut app = bare_app();
+    app.update();
+    app
+}
+fn bare_app() -> App {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .add_plugin(TransformPlugin)
+        .add_plugin(HierarchyPlugin)
+        .add_plugin(InputPlugin)
+        .add_plugin(AssetPlugin::default())
+        .add_asset::<Mesh>()
+        .add_plugin(ScenePlugin)
+        .add_plugin(VehiclePlugin::default())
+        .insert_resource(TimeUpdateStrategy::ManualInstant(Instant::now()));
+    app
+}
+/// Flat fixed box whose top face is at `y = 0`, stretching `half_size` in every direction.
+pub fn spawn_test_surface(app: &mut App, half_size: f32) -> Entity {
+    app.world
+        .spawn((
+            TransformBundle::from(Transform::from_xyz(0., -0.5, 0.)),
+            RigidBody::Fixed,
+            Collider::cuboid(half_size, 0.5, half_size),
+        ))
+        .id()
+}
+/// Runs `f` with `Commands` and the asset server, and applies the commands right away.
+pub fn run_commands<R>(app: &mut App, f: impl FnOnce(&mut Commands, &AssetServer) -> R) -> R {
+    app.world.resource_scope(|world, asset_server: Mut<AssetServer>| {
+        let mut queue = CommandQueue::default();
+        let mut commands = Commands::new(&mut queue, world);
+        let result = f(&mut commands, &asset_server);
+        queue.apply(world);
+        result
+    })
+}
+/// Spawns a car through `spawn_car`, the models fail to load without a renderer but the
+/// physics works all the same.
+pub fn spawn_test_car(app: &mut App, spec: &CarSpec, transform: Transform) -> Entity {
+    run_commands(app, |commands, asset_server| spawn_car(commands, asset_server, spec, transform))
+}
+/// Updates the app `frames` times, advancing the clock by exactly one physics step each
+/// time so every frame runs the fixed update once.
+pub fn step_frames(app: &mut App, frames: usize) {
+    let period = app.world.resource::<FixedTime>().period;
+    for _ in 0..frames {
+        let now = match *app.world.resource::<TimeUpdateStrategy>() {
+            TimeUpdateStrategy::ManualInstant(instant) => instant + period,
+            _ => Instant::now(),
+        };
+        app.insert_resource(TimeUpdateStrategy::ManualInstant(now));
+        app.update();
+    }
+}
@@ -0,0 +1,147 @@
+use std::path::Path;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    car_spawn::{despawn_car, spawn_car, CarSpec},
+    car_suspension::CarPhysics,
+    ron_file::{self, RonFileError},
+    vehicle_input::{HumanDriver, VehicleInput},
+};
+
+/// Where F6 saves the last recording and F7 loads it from.
+pub const REPLAY_PATH: &str = "recordings/last.replay.ron";
+
+/// Everything needed to play a run back exactly: the car, where it started, the seed and
+/// the input of every fixed step.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct InputRecording {
+    pub spec: CarSpec,
+    pub initial_transform: Transform,
+    pub seed: u64,
+    pub frames: Vec<VehicleInput>,
+}
+impl InputRecording {
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), RonFileError> {
+        ron_file::save(self, path)
+    }
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, RonFileError> {
+        ron_file::load(path)
+    }
+}
+/// Seed for anything random during a run, like terrain without a seed of its own, stored in
+/// recordings so a replay sees the same values.
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct SimulationSeed(pub u64);
+impl Default for SimulationSeed {
+    fn default() -> Self {
+        Self(rand::random())
+    }
+}
+/// Appends the input of the car to its recording on every fixed step.
+#[derive(Component)]
+pub struct InputRecorder(pub InputRecording);
+/// Feeds a recording into the car's input instead of the driver, one frame per fixed step.
+#[derive(Component)]
+pub struct InputReplay {
+    pub recording: InputRecording,
+    pub frame: usize,
+}
+impl InputReplay {
+    pub fn finished(&self) -> bool {
+        self.frame >= self.recording.frames.len()
+    }
+}
+/// Spawns a fresh car that records its input from the first step on, so the run can be
+/// replayed from the same starting state.
+pub fn start_recording(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    spec: &CarSpec,
+    transform: Transform,
+    seed: SimulationSeed,
+) -> Entity {
+    let car = spawn_car(commands, asset_server, spec, transform);
+    commands.entity(car).insert(InputRecorder(InputRecording {
+        spec: spec.clone(),
+        initial_transform: transform,
+        seed: seed.0,
+        frames: Vec::new(),
+    }));
+    car
+}
+/// Spawns the car of `recording` where it started and plays its input back.
+pub fn start_replay(commands: &mut Commands, asset_server: &AssetServer, recording: InputRecording) -> Entity {
+    commands.insert_resource(SimulationSeed(recording.seed));
+    let car = spawn_car(commands, asset_server, &recording.spec, recording.initial_transform);
+    commands.entity(car).insert(InputReplay { recording, frame: 0 });
+    car
+}
+type PlayerCarQuery<'a> = (
+    Entity,
+    &'a CarPhysics,
+    &'a Transform,
+    Option<&'a InputRecorder>,
+    Option<&'a InputReplay>,
+    Option<&'a HumanDriver>,
+);
+type PlayerCarFilter = Or<(With<HumanDriver>, With<InputReplay>)>;
+/// F5 respawns the player's car and starts recording it, F6 saves the recording and
+/// replays it, F7 replays the recording saved in `REPLAY_PATH`.
+pub fn replay_controls(
+    mut commands: Commands,
+    keys: Res<Input<KeyCode>>,
+    asset_server: Res<AssetServer>,
+    seed: Res<SimulationSeed>,
+    car_query: Query<PlayerCarQuery, PlayerCarFilter>,
+) {
+    if keys.just_pressed(KeyCode::F5) {
+        for (entity, car_physics, transform, recorder, replay, driver) in car_query.iter() {
+            let spec = recorder
+                .map(|recorder| recorder.0.spec.clone())
+                .or_else(|| replay.map(|replay| replay.recording.spec.clone()))
+                .unwrap_or_default();
+            despawn_car(&mut commands, entity, car_physics);
+            let car = start_recording(&mut commands, &asset_server, &spec, *transform, *seed);
+            commands.entity(car).insert(driver.copied().unwrap_or_default());
+            info!("Recording input");
+        }
+    } else if keys.just_pressed(KeyCode::F6) {
+        for (entity, car_physics, _, recorder, ..) in car_query.iter() {
+            let Some(recorder) = recorder else {
+                continue;
+            };
+            match recorder.0.save(REPLAY_PATH) {
+                Ok(()) => info!("Saved {} frames of input to {REPLAY_PATH}", recorder.0.frames.len()),
+                Err(err) => error!("Could not save the recording to {REPLAY_PATH}: {err}"),
+            }
+            despawn_car(&mut commands, entity, car_physics);
+            start_replay(&mut commands, &asset_server, recorder.0.clone());
+        }
+    } else if keys.just_pressed(KeyCode::F7) {
+        match InputRecording::load(REPLAY_PATH) {
+            Ok(recording) => {
+                for (entity, car_physics, ..) in car_query.iter() {
+                    despawn_car(&mut commands, entity, car_physics);
+                }
+                info!("Replaying {} frames of input from {REPLAY_PATH}", recording.frames.len());
+                start_replay(&mut commands, &asset_server, recording);
+            }
+            Err(err) => error!("Could not load the recording from {REPLAY_PATH}: {err}"),
+        }
+    }
+}
+pub fn replay_input(mut car_query: Query<(&mut VehicleInput, &mut InputReplay)>) {
+    for (mut input, mut replay) in car_query.iter_mut() {
+        *input = replay.recording.frames.get(replay.frame).copied().unwrap_or_default();
+        replay.frame += 1;
+    }
+}
+/// Runs right before `car_controls`, so it sees the latched button presses before they
+/// are consumed.
+pub fn record_input(mut car_query: Query<(&VehicleInput, &mut InputRecorder)>) {
+    for (input, mut recorder) in car_query.iter_mut() {
+        recorder.0.frames.push(*input);
+    }
+}
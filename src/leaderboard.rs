@@ -0,0 +1,188 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    car_spawn::CarSpec,
+    checkpoints::RaceFinished,
+    ron_file::{self, RonFileError},
+    race_state::{RaceRestarted, RaceState},
+    timer_text::Completion,
+    track::{ActiveTrack, TrackManifest},
+    vehicle_input::HumanDriver,
+};
+
+/// Where the finish times are kept between sessions.
+pub const LEADERBOARD_PATH: &str = "saves/leaderboard.ron";
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LeaderboardEntry {
+    pub track: String,
+    pub car: String,
+    pub player: String,
+    pub time: f32,
+    /// Seconds since the Unix epoch.
+    pub date: u64,
+}
+/// Every valid finish time, on all tracks and cars.
+#[derive(Resource, Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Leaderboard {
+    pub entries: Vec<LeaderboardEntry>,
+}
+impl Leaderboard {
+    /// Fastest `count` entries of `car` on `track`.
+    pub fn top(&self, track: &str, car: &str, count: usize) -> Vec<&LeaderboardEntry> {
+        let mut entries: Vec<_> = self
+            .entries
+            .iter()
+            .filter(|entry| entry.track == track && entry.car == car)
+            .collect();
+        entries.sort_by(|a, b| a.time.total_cmp(&b.time));
+        entries.truncate(count);
+        entries
+    }
+    /// Adds `entry` and returns its place among the times of the same track and car,
+    /// starting at 1. Ties go to the older time.
+    pub fn insert(&mut self, entry: LeaderboardEntry) -> usize {
+        let place = 1 + self
+            .entries
+            .iter()
+            .filter(|other| other.track == entry.track && other.car == entry.car && other.time <= entry.time)
+            .count();
+        self.entries.push(entry);
+        place
+    }
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), RonFileError> {
+        ron_file::save(self, path)
+    }
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, RonFileError> {
+        ron_file::load(path)
+    }
+}
+/// File the leaderboard is read from and written back to.
+#[derive(Resource)]
+pub struct LeaderboardFile(pub PathBuf);
+/// Name entered on the leaderboard, the user's login name unless set otherwise.
+#[derive(Resource)]
+pub struct PlayerName(pub String);
+impl Default for PlayerName {
+    fn default() -> Self {
+        let name = std::env::var("USER").or_else(|_| std::env::var("USERNAME"));
+        Self(name.unwrap_or_else(|_| "Player".to_string()))
+    }
+}
+/// Outcome of the player's last race, for the results screen.
+#[derive(Resource, Default)]
+pub struct RaceResult(pub Option<FinishedRun>);
+#[derive(Clone, Debug)]
+pub struct FinishedRun {
+    pub entry: LeaderboardEntry,
+    /// Place on the leaderboard, `None` when the run didn't count.
+    pub place: Option<usize>,
+}
+/// Keeps the player's finish times in the file at `path`.
+pub struct LeaderboardPlugin {
+    pub path: PathBuf,
+}
+impl Default for LeaderboardPlugin {
+    fn default() -> Self {
+        Self {
+            path: PathBuf::from(LEADERBOARD_PATH),
+        }
+    }
+}
+impl Plugin for LeaderboardPlugin {
+    fn build(&self, app: &mut App) {
+        let leaderboard = if self.path.exists() {
+            Leaderboard::load(&self.path).unwrap_or_else(|err| {
+                error!("Could not load the leaderboard from {}: {err}", self.path.display());
+                Leaderboard::default()
+            })
+        } else {
+            Leaderboard::default()
+        };
+        app.insert_resource(leaderboard)
+            .insert_resource(LeaderboardFile(self.path.clone()))
+            .init_resource::<PlayerName>()
+            .init_resource::<RaceResult>()
+            .add_system(submit_finish_time)
+            .add_system(clear_race_result)
+            .add_system(forget_race_result.in_schedule(OnEnter(RaceState::TrackSelect)));
+    }
+}
+/// Puts the player's time on the leaderboard when they finish, if the run counts.
+#[allow(clippy::too_many_arguments)]
+pub fn submit_finish_time(
+    mut race_finished: EventReader<RaceFinished>,
+    completion: Res<Completion>,
+    player_name: Res<PlayerName>,
+    manifests: Res<Assets<TrackManifest>>,
+    active_track: Option<Res<ActiveTrack>>,
+    car_query: Query<&CarSpec, With<HumanDriver>>,
+    mut leaderboard: ResMut<Leaderboard>,
+    file: Res<LeaderboardFile>,
+    mut result: ResMut<RaceResult>,
+) {
+    for event in race_finished.iter() {
+        let Ok(spec) = car_query.get(event.car) else {
+            continue;
+        };
+        let track = active_track
+            .as_ref()
+            .and_then(|active_track| manifests.get(&active_track.0))
+            .map(|manifest| manifest.name.clone())
+            .unwrap_or_default();
+        let date = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or_default();
+        let entry = LeaderboardEntry {
+            track,
+            car: spec.name.clone(),
+            player: player_name.0.clone(),
+            time: completion.elapsed,
+            date,
+        };
+        let place = match completion.valid_time() {
+            Some(_) => {
+                let place = leaderboard.insert(entry.clone());
+                if let Err(err) = leaderboard.save(&file.0) {
+                    error!("Could not save the leaderboard to {}: {err}", file.0.display());
+                }
+                Some(place)
+            }
+            None => {
+                warn!("The run did not go through uninterrupted, its time is not kept");
+                None
+            }
+        };
+        result.0 = Some(FinishedRun { entry, place });
+    }
+}
+pub fn clear_race_result(mut restarted: EventReader<RaceRestarted>, mut result: ResMut<RaceResult>) {
+    if restarted.iter().count() > 0 {
+        result.0 = None;
+    }
+}
+/// The result belongs to the track being left.
+pub fn forget_race_result(mut result: ResMut<RaceResult>) {
+    result.0 = None;
+}
+/// Formats seconds since the Unix epoch as a `YYYY-MM-DD` date in UTC.
+pub fn format_date(timestamp: u64) -> String {
+    // civil from days, counting in 400 year eras starting on March 1st
+    let days = (timestamp / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{year:04}-{month:02}-{day:02}")
+}
@@ -0,0 +1,157 @@
+use bevy::input::InputSystem;
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use car_tuning::{CarTuning, CarTuningLoader};
+use surface::{SurfaceTable, SurfaceTableLoader};
+use vehicle_input::{InputBindings, InputBindingsFile, InputBindingsLoader, Rebinding};
+
+pub mod vector_operations;
+pub mod car_suspension;
+pub mod car_tires;
+pub mod surface;
+pub mod surface_effects;
+pub mod car_tuning;
+pub mod car_camera;
+pub mod car_controls;
+pub mod car_drivetrain;
+pub mod car_spawn;
+pub mod car_respawn;
+pub mod vehicle_input;
+pub mod ui_management;
+pub mod timer_text;
+pub mod headless;
+pub mod ron_file;
+pub mod input_replay;
+pub mod ghost_car;
+pub mod checkpoints;
+pub mod track;
+pub mod track_scene;
+pub mod terrain;
+pub mod road;
+pub mod leaderboard;
+pub mod race_state;
+pub mod loading;
+
+pub const PHYSICS_TIMESTEP: f32 = 1. / 60.;
+
+/// Ordering of the vehicle systems, so apps can schedule their own systems around them.
+#[derive(SystemSet, Clone, Debug, Hash, PartialEq, Eq)]
+pub enum VehicleSet {
+    /// Reads the keyboard and gamepads into `VehicleInput`, in `CoreSet::PreUpdate`.
+    Input,
+    /// The fixed step systems below run in this order, before Rapier steps.
+    Suspension,
+    Controls,
+    Drivetrain,
+    Tires,
+    /// Follows the cars through the checkpoints and times the player once Rapier has stepped,
+    /// filled by `RacePlugin`.
+    Race,
+    /// Moves the cameras after their cars, in `CoreSet::Update`.
+    Camera,
+}
+/// Cars, their input and cameras, stepped together with Rapier on a fixed timestep.
+/// Adds `RapierPhysicsPlugin` itself, so apps using it should not add it again. The tracks
+/// and the race around the cars are left to `RacePlugin`, the controls are live without it.
+pub struct VehiclePlugin {
+    pub physics_timestep: f32,
+}
+impl Default for VehiclePlugin {
+    fn default() -> Self {
+        Self {
+            physics_timestep: PHYSICS_TIMESTEP,
+        }
+    }
+}
+impl Plugin for VehiclePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugin(RapierPhysicsPlugin::<NoUserData>::default().with_default_system_setup(false))
+            .insert_resource(FixedTime::new_from_secs(self.physics_timestep))
+            .insert_resource(RapierConfiguration {
+                timestep_mode: TimestepMode::Fixed { dt: self.physics_timestep, substeps: 1 },
+                ..default()
+            })
+            .edit_schedule(CoreSchedule::FixedUpdate, |schedule| {
+                // step rapier inside the fixed update so the vehicle forces and the
+                // simulation always advance by the same amount of time
+                schedule
+                    .configure_sets(
+                        (
+                            PhysicsSet::SyncBackend,
+                            PhysicsSet::SyncBackendFlush,
+                            PhysicsSet::StepSimulation,
+                            PhysicsSet::Writeback,
+                        )
+                            .chain(),
+                    )
+                    .configure_sets(
+                        (
+                            VehicleSet::Suspension,
+                            VehicleSet::Controls,
+                            VehicleSet::Drivetrain,
+                            VehicleSet::Tires,
+                        )
+                            .chain()
+                            .before(PhysicsSet::SyncBackend),
+                    )
+                    .configure_set(VehicleSet::Race.after(PhysicsSet::Writeback))
+                    .add_systems(RapierPhysicsPlugin::<NoUserData>::get_systems(PhysicsSet::SyncBackend).in_base_set(PhysicsSet::SyncBackend))
+                    .add_systems(RapierPhysicsPlugin::<NoUserData>::get_systems(PhysicsSet::SyncBackendFlush).in_base_set(PhysicsSet::SyncBackendFlush))
+                    .add_systems(RapierPhysicsPlugin::<NoUserData>::get_systems(PhysicsSet::StepSimulation).in_base_set(PhysicsSet::StepSimulation))
+                    .add_systems(RapierPhysicsPlugin::<NoUserData>::get_systems(PhysicsSet::Writeback).in_base_set(PhysicsSet::Writeback));
+            })
+            .add_asset::<CarTuning>()
+            .init_asset_loader::<CarTuningLoader>()
+            .add_system(car_tuning::apply_car_tuning)
+            .add_asset::<InputBindings>()
+            .init_asset_loader::<InputBindingsLoader>()
+            .init_resource::<InputBindings>()
+            .init_resource::<InputBindingsFile>()
+            .init_resource::<Rebinding>()
+            .init_resource::<input_replay::SimulationSeed>()
+            .init_resource::<car_respawn::RespawnSettings>()
+            .add_startup_system(vehicle_input::load_input_bindings)
+            .add_system(vehicle_input::apply_input_bindings)
+            .add_asset::<SurfaceTable>()
+            .init_asset_loader::<SurfaceTableLoader>()
+            .init_resource::<SurfaceTable>()
+            .add_startup_system(surface::load_surface_table)
+            .add_system(surface::apply_surface_table)
+            .add_system(vehicle_input::rebind_controls)
+            .add_systems(
+                (
+                    vehicle_input::assign_gamepads,
+                    vehicle_input::clear_vehicle_input,
+                    vehicle_input::keyboard_input,
+                    vehicle_input::gamepad_input,
+                )
+                    .chain()
+                    .in_set(VehicleSet::Input)
+                    .in_base_set(CoreSet::PreUpdate)
+                    .after(InputSystem),
+            )
+            .add_system(car_suspension::update_car_suspension.in_set(VehicleSet::Suspension).in_schedule(CoreSchedule::FixedUpdate))
+            .add_system(car_controls::car_controls.in_set(VehicleSet::Controls).in_schedule(CoreSchedule::FixedUpdate))
+            .add_system(car_drivetrain::update_drivetrain.in_set(VehicleSet::Drivetrain).in_schedule(CoreSchedule::FixedUpdate))
+            .add_system(car_tires::update_car_tires.in_set(VehicleSet::Tires).in_schedule(CoreSchedule::FixedUpdate))
+            .add_systems(
+                // respawned cars cast their wheels from where they were put back
+                (input_replay::replay_input, input_replay::record_input, car_respawn::respawn_cars)
+                    .chain()
+                    .before(VehicleSet::Suspension)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            .add_system(
+                car_respawn::track_grounded_poses
+                    .after(PhysicsSet::Writeback)
+                    .before(VehicleSet::Race)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            .add_systems(
+                (car_camera::assign_camera_target, car_camera::camera_follow)
+                    .chain()
+                    .in_set(VehicleSet::Camera),
+            );
+    }
+}
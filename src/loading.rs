@@ -0,0 +1,68 @@
+use bevy::{asset::LoadState, prelude::*};
+
+use crate::race_state::RaceState;
+
+/// Handles the race waits for before it can start, the meshes and scenes the track is built
+/// from. A missing one fails the load.
+#[derive(Resource, Default)]
+pub struct AssetsLoading(pub Vec<HandleUntyped>);
+#[derive(Resource, Clone, Copy, Debug, Default)]
+pub struct LoadingProgress {
+    pub loaded: usize,
+    pub total: usize,
+}
+impl LoadingProgress {
+    pub fn done(&self) -> bool {
+        self.loaded == self.total
+    }
+}
+/// What kept the race from loading, shown on the error screen.
+#[derive(Resource, Clone, Debug)]
+pub struct LoadFailure {
+    pub asset: String,
+    pub reason: String,
+}
+/// Counts the handles in `AssetsLoading` that have loaded, and gives up on the first one
+/// that failed.
+pub fn track_loading(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    loading: Res<AssetsLoading>,
+    mut progress: ResMut<LoadingProgress>,
+    mut next_state: ResMut<NextState<RaceState>>,
+) {
+    let mut loaded = 0;
+    for handle in loading.0.iter() {
+        match asset_server.get_load_state(handle) {
+            LoadState::Loaded => loaded += 1,
+            LoadState::Failed => {
+                let asset = asset_server
+                    .get_handle_path(handle)
+                    .map(|path| path.path().display().to_string())
+                    .unwrap_or_else(|| format!("{:?}", handle.id()));
+                fail_loading(&mut commands, &mut next_state, asset, "the file is missing or could not be read");
+                return;
+            }
+            LoadState::NotLoaded | LoadState::Loading | LoadState::Unloaded => {}
+        }
+    }
+    *progress = LoadingProgress {
+        loaded,
+        total: loading.0.len(),
+    };
+}
+/// Stops loading and shows `asset` with `reason` on the error screen.
+pub fn fail_loading(
+    commands: &mut Commands,
+    next_state: &mut NextState<RaceState>,
+    asset: impl Into<String>,
+    reason: impl Into<String>,
+) {
+    let failure = LoadFailure {
+        asset: asset.into(),
+        reason: reason.into(),
+    };
+    error!("Could not load {}: {}", failure.asset, failure.reason);
+    commands.insert_resource(failure);
+    next_state.set(RaceState::Failed);
+}
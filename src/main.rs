@@ -1,25 +1,26 @@
-use std::sync::atomic::AtomicBool;
-
 use bevy::prelude::*;
 
 use bevy::render::render_resource::{AddressMode, SamplerDescriptor, FilterMode};
-use bevy::render::texture::ImageSampler;
 use bevy::window::{WindowResolution, WindowMode};
-use bevy_inspector_egui::quick::WorldInspectorPlugin;
 use bevy_rapier3d::prelude::*;
-use car_camera::CameraFollow;
-use car_controls::CarController;
-use car_suspension::WheelInfo;
+use car_game::car_camera::CameraFollow;
+use car_game::car_spawn::{self, CarSpec};
+use car_game::vehicle_input::HumanDriver;
+use car_game::ghost_car::GhostCarPlugin;
+use car_game::input_replay::SimulationSeed;
+use car_game::leaderboard::LeaderboardPlugin;
+use car_game::loading::{fail_loading, track_loading, AssetsLoading, LoadingProgress};
+use car_game::race_state::{RacePlugin, RaceState};
+use car_game::road::Road;
+use car_game::terrain::{grow_terrain, GeneratedTerrain, TerrainSettings};
+use car_game::track::{self, ActiveTrack, TrackManifest, TrackMaterial};
+use car_game::surface::TriangleSurfaces;
+use car_game::surface_effects::SurfaceEffectsPlugin;
+use car_game::track_scene::SceneColliders;
+use car_game::{timer_text, ui_management, VehiclePlugin};
 use rand::rngs::ThreadRng;
 use rand::Rng;
 
-pub mod vector_operations;
-pub mod car_suspension;
-pub mod car_camera;
-pub mod car_controls;
-pub mod ui_management;
-pub mod timer_text;
-
 fn main() {
     App::new()
         .insert_resource(ClearColor(Color::MIDNIGHT_BLUE))
@@ -37,25 +38,40 @@ fn main() {
                 mag_filter: FilterMode::Nearest,
                 min_filter: FilterMode::Nearest,
                 mipmap_filter: FilterMode::Nearest,
+                // generated roads and terrain tile their textures
+                address_mode_u: AddressMode::Repeat,
+                address_mode_v: AddressMode::Repeat,
                 ..Default::default()
-                },..default()
+                },
             }
 
         ))
-        .add_plugin(RapierPhysicsPlugin::<NoUserData>::default())
+        .add_plugin(VehiclePlugin::default())
+        .add_plugin(RacePlugin)
+        .add_plugin(GhostCarPlugin)
+        .add_plugin(SurfaceEffectsPlugin)
+        .add_plugin(LeaderboardPlugin::default())
         //.add_plugin(RapierDebugRenderPlugin::default())
         //.add_plugin(WorldInspectorPlugin::default())
         .add_startup_system(setup_graphics)
-        .add_startup_system(setup_physics)
-        .add_system(car_suspension::update_car_suspension)
-        .add_system(car_camera::camera_follow)
-        .add_system(car_controls::car_controls.after(car_suspension::update_car_suspension))
-        .add_system(check_assets_ready)
+        .add_startup_system(track::load_track_list)
+        .add_system(setup_track.in_schedule(OnEnter(RaceState::Loading)))
+        .add_system(leave_track.in_schedule(OnEnter(RaceState::TrackSelect)))
+        .add_system(ui_management::initialize_track_select.in_schedule(OnEnter(RaceState::TrackSelect)))
+        .add_system(ui_management::update_track_select.in_set(OnUpdate(RaceState::TrackSelect)))
+        .add_system(ui_management::despawn_track_select.in_schedule(OnExit(RaceState::TrackSelect)))
+        .add_system(check_assets_ready.after(track_loading).in_set(OnUpdate(RaceState::Loading)))
+        .add_system(ui_management::initialize_loading_screen.in_schedule(OnEnter(RaceState::Loading)))
+        .add_system(ui_management::update_loading_screen.in_set(OnUpdate(RaceState::Loading)))
+        .add_system(ui_management::despawn_loading_screen.in_schedule(OnExit(RaceState::Loading)))
+        .add_system(ui_management::show_load_error.in_schedule(OnEnter(RaceState::Failed)))
+        .add_system(ui_management::despawn_load_error.in_schedule(OnExit(RaceState::Failed)))
         .insert_resource(MapStatus { loaded: false })
         .add_startup_system(ui_management::initialize_dialogue)
-        .add_system(timer_text::text_update_system)
-        .insert_resource(timer_text::Completion{finished : false,started : false})
-        .init_resource::<AssetsLoading>()
+        .add_startup_system(ui_management::initialize_race_state_text)
+        .add_system(timer_text::text_update_system.run_if(not(in_state(RaceState::Loading))))
+        .add_system(ui_management::race_state_text_update)
+        .add_system(ui_management::show_results_screen)
         .run();
 }
 
@@ -68,133 +84,111 @@ fn setup_graphics(mut commands: Commands) {
             ..Default::default()
         })
         .insert(CameraFollow {
+            target: None,
             camera_translation_speed: 1000.,
             fake_transform: Transform::from_xyz(0., 0., 0.),
             distance_behind: 10.,
         });
 }
-const CAR_SIZE: Vec3 = Vec3::new(0.5, 0.3, 0.935);
-pub fn setup_physics(
+/// Everything spawned for the current track, cleared when going back to the track selection.
+#[derive(Component)]
+pub struct TrackEntity;
+
+/// Starts loading the picked track, lights it and puts the player's car at its start.
+pub fn setup_track(
     asset_server: Res<AssetServer>,
+    manifests: Res<Assets<TrackManifest>>,
+    active_track: Res<ActiveTrack>,
     mut loading: ResMut<AssetsLoading>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
     mut commands: Commands,
+    mut next_state: ResMut<NextState<RaceState>>,
 ) {
+    let Some(manifest) = manifests.get(&active_track.0) else {
+        fail_loading(&mut commands, &mut next_state, "the track manifest", "it is not loaded");
+        return;
+    };
+    let lighting = manifest.lighting;
     commands.insert_resource(AmbientLight {
-        color: Color::WHITE,
-        brightness: 0.3,
+        color: Color::rgb(lighting.ambient_color.x, lighting.ambient_color.y, lighting.ambient_color.z),
+        brightness: lighting.ambient_brightness,
     });
-    let x_shape: Handle<Mesh> = asset_server.load("racetrack.glb#Mesh0/Primitive0");
-    loading.0.push(x_shape.clone_untyped());
+    // a fresh seed for the run, unseeded terrain gets new hills from it
+    commands.insert_resource(SimulationSeed::default());
+    // only what the track can't be built without, textures fill in once they are there
+    loading.0.clear();
+    for track_mesh in manifest.meshes.iter() {
+        let mesh: Handle<Mesh> = asset_server.load(track_mesh.mesh.as_str());
+        loading.0.push(mesh.clone_untyped());
+    }
+    for track_scene in manifest.scenes.iter() {
+        let scene: Handle<Scene> = asset_server.load(track_scene.scene.as_str());
+        loading.0.push(scene.clone_untyped());
+    }
 
     // directional 'sun' light
-    commands.spawn(DirectionalLightBundle {
-        directional_light: DirectionalLight {
-            illuminance: 10000.,
-            shadows_enabled: true,
-            ..default()
-        },
-        transform: Transform {
-            translation: Vec3::new(0.0, 2.0, 0.0),
-            rotation: Quat::from_rotation_x(-std::f32::consts::PI / 4.),
+    commands.spawn((
+        DirectionalLightBundle {
+            directional_light: DirectionalLight {
+                illuminance: lighting.sun_illuminance,
+                shadows_enabled: lighting.shadows,
+                ..default()
+            },
+            transform: Transform {
+                translation: Vec3::new(0.0, 2.0, 0.0),
+                rotation: lighting.sun_rotation,
+                ..default()
+            },
             ..default()
         },
-        ..default()
-    });
-    
-    let car_size = CAR_SIZE;
+        TrackEntity,
+    ));
 
-
-    let mut wheel_vec =Vec::new();
-    
-    for i in 0..4
-    {
-        let wheel_entity = commands.spawn(SceneBundle {
-            transform: Transform::from_xyz(0.0, 0.0, 0.0),
-            scene: asset_server.load("wheel.glb#Scene0"),
-            ..default()
-        }).id();
-    
-        wheel_vec.push(WheelInfo{entity:wheel_entity,hit:false});
+    let car = car_spawn::spawn_car(&mut commands, &asset_server, &CarSpec::default(), manifest.spawn.transform());
+    commands.entity(car).insert(HumanDriver::default());
+}
+/// Removes the map and lights of the track being left, the cars are taken care of by
+/// `race_state::leave_race`.
+fn leave_track(
+    mut commands: Commands,
+    mut map_status: ResMut<MapStatus>,
+    track_query: Query<Entity, With<TrackEntity>>,
+) {
+    for entity in track_query.iter() {
+        commands.entity(entity).despawn_recursive();
     }
-    
-
-
-    commands
-        .spawn((
-            SceneBundle {
-                transform: Transform::from_xyz(0., 1., 0.),
-                //mesh: meshes.add(Mesh::from(shape::Cube { ..default() })),
-                scene : asset_server.load("car.glb#Scene0"),
-                //material: materials.add(Color::rgb(1., 1., 1.).into()),
-                ..default()
-            },
-            //TransformBundle::from(Transform::from_xyz(0., 5., 0.)),
-            RigidBody::Dynamic,
-            Collider::cuboid(car_size.x,car_size.y,car_size.z),
-        ))
-        .insert(car_suspension::CarPhysics {
-            wheels_stationary_animation_speed : 10.,
-            wheels_animation_speed : 3.,
-            wheel_infos : wheel_vec,
-            plane : Vec3::ZERO,
-            car_size : CAR_SIZE,
-            car_transform_camera: Transform::from_xyz(0., 0., 0.),
-        })
-        .insert(CarController {
-            car_linear_damping : 0.5,
-            rotate_to_rotation: Quat::IDENTITY,
-            slerp_speed: 5.,
-            rotated_last_frame: false,
-            center_of_mass_altered: false,
-            speed: 50000.,
-            rotate_speed: 5200.,
-        })
-        .insert(Velocity { ..default() })
-        .insert(ExternalImpulse {
-            impulse: Vec3::new(0., 0., 0.),
-            torque_impulse: Vec3::new(0., 0., 0.),
-        })
-        .insert(ExternalForce {
-            force: Vec3::new(0., 0., 0.),
-            torque: Vec3::new(0., 0., 0.),
-        })
-        .insert(GravityScale(1.))
-        .insert(Damping {
-            linear_damping: 0.,
-            angular_damping: 3.,
-        })
-        .insert(Ccd::enabled());
+    map_status.loaded = false;
 }
-
-
-#[derive(Resource, Default)]
-pub struct AssetsLoading(Vec<HandleUntyped>);
-
+/// Builds the map once everything in `AssetsLoading` is there, and starts the countdown
+/// once the scenes have been spawned and given their colliders.
+#[allow(clippy::too_many_arguments)]
 fn check_assets_ready(
-    map_status: ResMut<MapStatus>,
-    commands: Commands,
+    mut commands: Commands,
+    mut map_status: ResMut<MapStatus>,
+    progress: Res<LoadingProgress>,
     server: Res<AssetServer>,
+    manifests: Res<Assets<TrackManifest>>,
+    active_track: Res<ActiveTrack>,
+    seed: Res<SimulationSeed>,
     meshes: ResMut<Assets<Mesh>>,
     materials: ResMut<Assets<StandardMaterial>>,
-    loading: Res<AssetsLoading>,
+    pending_scenes: Query<(), With<SceneColliders>>,
+    mut next_state: ResMut<NextState<RaceState>>,
 ) {
-    use bevy::asset::LoadState;
-    static SETUP_PHYSICS_CALLED: AtomicBool = AtomicBool::new(false);
-    match server.get_group_load_state(loading.0.iter().map(|h| h.id())) {
-        LoadState::Failed => {
-            // one of our assets had an error
-        }
-        LoadState::Loaded => {
-            if !SETUP_PHYSICS_CALLED.load(std::sync::atomic::Ordering::Relaxed) {
-                setup_map(commands, map_status, server, meshes, materials);
-                SETUP_PHYSICS_CALLED.store(true, std::sync::atomic::Ordering::Relaxed);
-            }
-        }
-        _ => {
-            // NotLoaded/Loading: not fully ready yet
+    if !progress.done() {
+        return;
+    }
+    if map_status.loaded {
+        if pending_scenes.is_empty() {
+            next_state.set(RaceState::Countdown);
         }
+        return;
+    }
+    let Some(manifest) = manifests.get(&active_track.0) else {
+        return;
+    };
+    match setup_map(&mut commands, &server, manifest, *seed, meshes, materials) {
+        Ok(()) => map_status.loaded = true,
+        Err((asset, reason)) => fail_loading(&mut commands, &mut next_state, asset, reason),
     }
 }
 #[derive(Resource)]
@@ -203,53 +197,127 @@ pub struct MapStatus {
 }
 
 
+/// Spawns the meshes, terrain and road of the track with colliders of the same shape, or names
+/// the mesh that could not be used. Scenes get their colliders once they are spawned.
 fn setup_map(
-    mut commands: Commands,
-    mut map_status: ResMut<MapStatus>,
-    asset_server: Res<AssetServer>,
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    manifest: &TrackManifest,
+    seed: SimulationSeed,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
-) {
-    let mesh_handle: Handle<Mesh> = asset_server.load("racetrack.glb#Mesh0/Primitive0");
+) -> Result<(), (String, String)> {
+    for track_mesh in manifest.meshes.iter() {
+        let fail = |reason: &str| (track_mesh.mesh.clone(), reason.to_string());
+        let mesh_handle: Handle<Mesh> = asset_server.load(track_mesh.mesh.as_str());
+        let m = meshes.get(&mesh_handle).ok_or_else(|| fail("the file has no such mesh"))?;
+        let mut map_mesh = m.clone();
+        if let Err(err) = Mesh::generate_tangents(&mut map_mesh) {
+            warn!("Could not generate tangents for {}: {err}", track_mesh.mesh);
+        }
 
-    let m = meshes.get(&mesh_handle);
-    let mut map_mesh = m.unwrap().clone();
-    Mesh::generate_tangents(&mut map_mesh);
+        let x_shape = Collider::from_bevy_mesh(m, &ComputedColliderShape::TriMesh)
+            .ok_or_else(|| fail("no collider could be built from the mesh"))?;
+        let triangles = if track_mesh.painted {
+            Some(TriangleSurfaces::from_vertex_colors(m).ok_or_else(|| fail("it is painted but has no vertex colors"))?)
+        } else {
+            None
+        };
+        let ground_mat = materials.add(track_material(asset_server, &track_mesh.material));
 
-    let x_shape = Collider::from_bevy_mesh(m.unwrap(), &ComputedColliderShape::TriMesh).unwrap();
-    if Collider::from_bevy_mesh(m.unwrap(), &ComputedColliderShape::TriMesh).is_none() {
-        println!("{}", "the mesh failed to load");
+        let map = commands
+            .spawn((
+                RigidBody::Fixed,
+                PbrBundle {
+                    transform: Transform::from_xyz(0., 0., 0.).with_scale(Vec3::new(1., 1., 1.)),
+                    mesh: meshes.add(map_mesh),
+                    material: ground_mat,
+                    ..default()
+                },
+                TrackEntity,
+            ))
+            .insert(x_shape)
+            .insert(track_mesh.surface)
+            .id();
+        if let Some(triangles) = triangles {
+            commands.entity(map).insert(triangles);
+        }
     }
-    let texture_handle = asset_server.load("sand.png");
-    let normal_handle = asset_server.load("sand_normal.png");
-    let ground_mat = materials.add(StandardMaterial {
-        normal_map_texture: Some(normal_handle.clone()),
-        base_color: Color::rgb(1.2,1.2,1.),
-        perceptual_roughness: 0.5,
-        base_color_texture: Some(texture_handle.clone()),
-        cull_mode: None,
-        unlit: false,
-        ..default()
-    });
-
-    //println!("{}",x_shape);
-    commands
-        .spawn((
+    if let Some(settings) = manifest.terrain.as_ref() {
+        settings.validate().map_err(|reason| ("the terrain".to_string(), reason))?;
+        let seed = settings.seed.unwrap_or(seed.0);
+        spawn_terrain(commands, asset_server, manifest, settings, seed, &mut meshes, &mut materials);
+    }
+    if let Some(settings) = manifest.road.as_ref() {
+        let road = Road::generate(settings).map_err(|reason| ("the road".to_string(), reason))?;
+        let mut road_mesh = road.mesh();
+        if let Err(err) = Mesh::generate_tangents(&mut road_mesh) {
+            warn!("Could not generate tangents for the road: {err}");
+        }
+        commands.spawn((
             RigidBody::Fixed,
             PbrBundle {
-                transform: Transform::from_xyz(0., 0., 0.).with_scale(Vec3::new(1., 1., 1.)),
-                mesh: meshes.add(map_mesh),
-                material: ground_mat,
-                //scene: asset_server.load("map.glb#Scene0"),
+                mesh: meshes.add(road_mesh),
+                material: materials.add(track_material(asset_server, &settings.material)),
                 ..default()
             },
-        ))
-        .insert(x_shape);
-    map_status.loaded = true;
+            road.collider(),
+            settings.surface,
+            TrackEntity,
+        ));
+    }
+    for track_scene in manifest.scenes.iter() {
+        commands.spawn((
+            RigidBody::Fixed,
+            SceneBundle {
+                scene: asset_server.load(track_scene.scene.as_str()),
+                transform: track_scene.transform(),
+                ..default()
+            },
+            SceneColliders,
+            TrackEntity,
+        ));
+    }
+    Ok(())
+}
+/// Spawns the hills of `settings` grown from `seed`, with a collider of the same shape.
+fn spawn_terrain(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    manifest: &TrackManifest,
+    settings: &TerrainSettings,
+    seed: u64,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+) {
+    let (terrain, terrain_mesh) = grow_terrain(manifest, settings, seed);
+    commands.spawn((
+        RigidBody::Fixed,
+        PbrBundle {
+            mesh: meshes.add(terrain_mesh),
+            material: materials.add(track_material(asset_server, &settings.material)),
+            ..default()
+        },
+        terrain.collider(),
+        settings.surface,
+        GeneratedTerrain { seed },
+        TrackEntity,
+    ));
+}
+fn track_material(asset_server: &AssetServer, material: &TrackMaterial) -> StandardMaterial {
+    StandardMaterial {
+        normal_map_texture: material.normal_map_texture.as_ref().map(|texture| asset_server.load(texture.as_str())),
+        base_color: Color::rgb(material.base_color.x, material.base_color.y, material.base_color.z),
+        perceptual_roughness: material.perceptual_roughness,
+        base_color_texture: material.base_color_texture.as_ref().map(|texture| asset_server.load(texture.as_str())),
+        cull_mode: None,
+        unlit: false,
+        ..default()
+    }
 }
 pub fn neg_or_pos(rng: &mut ThreadRng) -> i32 {
     if rng.gen_range(0..2) == 1 {
         return 1;
     }
-    return -1;
+    -1
 }
@@ -0,0 +1,215 @@
+use bevy::prelude::*;
+
+use crate::{
+    car_spawn::{despawn_car, spawn_car, CarSpec},
+    car_suspension::CarPhysics,
+    car_respawn,
+    checkpoints::{self, CheckpointPassed, LapCompleted, RaceFinished, WrongWay},
+    input_replay, loading, terrain,
+    timer_text::{self, Completion},
+    track::{self, TrackList, TrackManifest, TrackManifestLoader},
+    track_scene,
+    vehicle_input::{self, HumanDriver, Rebinding, VehicleInput},
+    VehicleSet,
+};
+
+/// Seconds counted down before the race starts.
+pub const COUNTDOWN_SECONDS: f32 = 3.;
+const PAUSE_KEY: KeyCode = KeyCode::P;
+const PAUSE_BUTTON: GamepadButtonType = GamepadButtonType::Start;
+const RESTART_KEY: KeyCode = KeyCode::R;
+const TRACK_SELECT_KEY: KeyCode = KeyCode::Escape;
+
+/// Game flow around the cars: the states of a race with its countdown, pause and restart,
+/// the tracks and their loading, the checkpoints and timing, and the replay keys. Needs
+/// `VehiclePlugin`.
+pub struct RacePlugin;
+impl Plugin for RacePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_state::<RaceState>()
+            .init_resource::<StartTransform>()
+            .init_resource::<Completion>()
+            .add_event::<RaceRestarted>()
+            .add_asset::<TrackManifest>()
+            .init_asset_loader::<TrackManifestLoader>()
+            .init_resource::<TrackList>()
+            .add_system(track::apply_track_settings)
+            .add_system(track_scene::build_scene_colliders)
+            .add_event::<CheckpointPassed>()
+            .add_event::<LapCompleted>()
+            .add_event::<RaceFinished>()
+            .add_event::<WrongWay>()
+            .add_system(checkpoints::spawn_checkpoints)
+            .init_resource::<loading::AssetsLoading>()
+            .init_resource::<loading::LoadingProgress>()
+            .add_system(loading::track_loading.in_set(OnUpdate(RaceState::Loading)))
+            .add_system(pause_time.in_schedule(OnEnter(RaceState::Loading)))
+            .add_system(unpause_time.in_schedule(OnExit(RaceState::Loading)))
+            .add_system(pause_time.in_schedule(OnEnter(RaceState::Failed)))
+            .add_system(unpause_time.in_schedule(OnExit(RaceState::Failed)))
+            .add_systems((leave_race, checkpoints::despawn_checkpoints).in_schedule(OnEnter(RaceState::TrackSelect)))
+            .add_system(start_countdown.in_schedule(OnEnter(RaceState::Countdown)))
+            .add_system(tick_countdown.in_set(OnUpdate(RaceState::Countdown)))
+            .add_system(start_race.in_schedule(OnEnter(RaceState::Racing)))
+            .add_system(finish_race.in_set(OnUpdate(RaceState::Racing)))
+            .add_system(pause_time.in_schedule(OnEnter(RaceState::Paused)))
+            .add_system(unpause_time.in_schedule(OnExit(RaceState::Paused)))
+            .add_systems((race_controls, restart_race).chain().before(vehicle_input::rebind_controls))
+            // a replay brings its own seed, and the terrain grows again from it
+            .add_systems((input_replay::replay_controls, apply_system_buffers, terrain::reseed_terrain).chain())
+            .add_system(
+                lock_controls
+                    .after(VehicleSet::Input)
+                    .in_base_set(CoreSet::PreUpdate)
+                    .run_if(not(in_state(RaceState::Racing))),
+            )
+            .add_systems(
+                (
+                    timer_text::advance_race_clock,
+                    checkpoints::update_race_progress,
+                    checkpoints::detect_wrong_way,
+                    car_respawn::track_checkpoint_respawns,
+                    checkpoints::finish_player_race,
+                    timer_text::record_lap_times,
+                )
+                    .chain()
+                    .in_set(VehicleSet::Race)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            );
+    }
+}
+#[derive(States, Clone, Copy, Debug, Default, Hash, PartialEq, Eq)]
+pub enum RaceState {
+    /// Picking the track to race on.
+    #[default]
+    TrackSelect,
+    /// Waiting for the track, left by the app once its collider exists. Time stands still
+    /// until then, so nothing drives or falls through the missing ground.
+    Loading,
+    /// Something the race needs could not be loaded, see `LoadFailure`.
+    Failed,
+    /// 3-2-1-GO, with the controls locked.
+    Countdown,
+    Racing,
+    /// Time stands still, which stops the physics along with everything else.
+    Paused,
+    /// The player crossed the finish, the car rolls out without controls.
+    Finished,
+}
+#[derive(Resource)]
+pub struct Countdown {
+    pub remaining: f32,
+}
+impl Default for Countdown {
+    fn default() -> Self {
+        Self {
+            remaining: COUNTDOWN_SECONDS,
+        }
+    }
+}
+/// Where the player's car is put when the race is restarted.
+#[derive(Resource, Default)]
+pub struct StartTransform(pub Transform);
+/// Sent when the race starts over, for anything that keeps state about the run.
+pub struct RaceRestarted;
+
+/// Every countdown starts a new run, only the best lap is kept.
+pub fn start_countdown(mut commands: Commands, mut completion: ResMut<Completion>) {
+    commands.insert_resource(Countdown::default());
+    *completion = Completion {
+        best_lap: completion.best_lap.take(),
+        ..default()
+    };
+}
+pub fn tick_countdown(
+    time: Res<Time>,
+    mut countdown: ResMut<Countdown>,
+    mut next_state: ResMut<NextState<RaceState>>,
+) {
+    countdown.remaining -= time.delta_seconds();
+    if countdown.remaining <= 0. {
+        next_state.set(RaceState::Racing);
+    }
+}
+/// Starts the timer at GO, and keeps it going when coming back from a pause.
+pub fn start_race(mut completion: ResMut<Completion>) {
+    completion.started = true;
+}
+pub fn finish_race(completion: Res<Completion>, mut next_state: ResMut<NextState<RaceState>>) {
+    if completion.finished {
+        next_state.set(RaceState::Finished);
+    }
+}
+/// Releases everything the player holds while they are not racing.
+pub fn lock_controls(mut inputs: Query<&mut VehicleInput, With<HumanDriver>>) {
+    for mut input in inputs.iter_mut() {
+        *input = VehicleInput::default();
+    }
+}
+pub fn pause_time(mut time: ResMut<Time>) {
+    time.pause();
+}
+pub fn unpause_time(mut time: ResMut<Time>) {
+    time.unpause();
+}
+/// P or the gamepad's start button pauses and resumes the race, R restarts it and Escape
+/// goes back to the track selection once the race is paused or over.
+pub fn race_controls(
+    keys: Res<Input<KeyCode>>,
+    buttons: Res<Input<GamepadButton>>,
+    gamepads: Res<Gamepads>,
+    rebinding: Res<Rebinding>,
+    state: Res<State<RaceState>>,
+    mut next_state: ResMut<NextState<RaceState>>,
+    mut restarted: EventWriter<RaceRestarted>,
+) {
+    // the keys are being bound to actions
+    if rebinding.action.is_some() {
+        return;
+    }
+    let pause = keys.just_pressed(PAUSE_KEY)
+        || gamepads
+            .iter()
+            .any(|gamepad| buttons.just_pressed(GamepadButton::new(gamepad, PAUSE_BUTTON)));
+    match state.0 {
+        RaceState::Racing if pause => next_state.set(RaceState::Paused),
+        RaceState::Paused if pause => next_state.set(RaceState::Racing),
+        RaceState::Racing | RaceState::Paused | RaceState::Finished if keys.just_pressed(RESTART_KEY) => {
+            restarted.send(RaceRestarted);
+            next_state.set(RaceState::Countdown);
+        }
+        RaceState::Paused | RaceState::Finished | RaceState::Failed if keys.just_pressed(TRACK_SELECT_KEY) => {
+            next_state.set(RaceState::TrackSelect);
+        }
+        _ => {}
+    }
+}
+/// Puts a fresh player's car at the start with the same driver, the run itself is cleared by
+/// the countdown.
+pub fn restart_race(
+    mut commands: Commands,
+    mut restarted: EventReader<RaceRestarted>,
+    asset_server: Res<AssetServer>,
+    start: Res<StartTransform>,
+    car_query: Query<(Entity, &CarPhysics, &CarSpec, &HumanDriver)>,
+) {
+    if restarted.iter().count() == 0 {
+        return;
+    }
+    for (entity, car_physics, spec, driver) in car_query.iter() {
+        despawn_car(&mut commands, entity, car_physics);
+        let car = spawn_car(&mut commands, &asset_server, spec, start.0);
+        commands.entity(car).insert(*driver);
+    }
+}
+/// Clears away the cars and the run of the last track, including its best lap.
+pub fn leave_race(
+    mut commands: Commands,
+    mut completion: ResMut<Completion>,
+    car_query: Query<(Entity, &CarPhysics)>,
+) {
+    for (entity, car_physics) in car_query.iter() {
+        despawn_car(&mut commands, entity, car_physics);
+    }
+    *completion = Completion::default();
+}
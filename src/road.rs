@@ -0,0 +1,242 @@
+use bevy::{
+    prelude::*,
+    render::{mesh::Indices, render_resource::PrimitiveTopology},
+};
+use bevy_rapier3d::prelude::*;
+use serde::Deserialize;
+
+use crate::{
+    checkpoints::CheckpointSpec,
+    surface::Surface,
+    track::{SpawnPoint, TrackMaterial},
+};
+
+/// How far behind the start line the car is put, and how high above the road.
+const SPAWN_BEHIND: f32 = 6.;
+const SPAWN_LIFT: f32 = 1.;
+/// Checkpoints reach this far past the edges of the road.
+const CHECKPOINT_MARGIN: f32 = 2.;
+const CHECKPOINT_HEIGHT: f32 = 6.;
+
+/// Control point of a road, the road passes through its translation.
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct RoadPoint {
+    pub translation: Vec3,
+    pub width: f32,
+    /// Roll of the road around its direction in radians, positive raises the right edge.
+    #[serde(default)]
+    pub banking: f32,
+}
+/// Closed loop of road through its control points, smoothed with a Catmull-Rom spline.
+/// The start line is at the first point, and the road goes on towards the second.
+#[derive(Clone, Debug, Deserialize)]
+pub struct RoadSettings {
+    pub points: Vec<RoadPoint>,
+    /// Straight pieces the road is split into between two control points.
+    #[serde(default = "default_subdivisions")]
+    pub subdivisions: u32,
+    /// Checkpoints spread evenly along the road, starting with the start line. With none the
+    /// manifest's own checkpoints are used.
+    #[serde(default)]
+    pub checkpoints: u32,
+    /// Put the car just behind the start line instead of at the manifest's spawn point.
+    #[serde(default = "default_place_spawn")]
+    pub place_spawn: bool,
+    /// Length of road covered by the texture before it repeats, across as well as along.
+    #[serde(default = "default_texture_length")]
+    pub texture_length: f32,
+    #[serde(default)]
+    pub surface: Surface,
+    #[serde(default)]
+    pub material: TrackMaterial,
+}
+fn default_subdivisions() -> u32 {
+    12
+}
+fn default_place_spawn() -> bool {
+    true
+}
+fn default_texture_length() -> f32 {
+    10.
+}
+impl RoadSettings {
+    /// Road through `points` with everything else left at its default.
+    pub fn new(points: Vec<RoadPoint>) -> Self {
+        Self {
+            points,
+            subdivisions: default_subdivisions(),
+            checkpoints: 0,
+            place_spawn: default_place_spawn(),
+            texture_length: default_texture_length(),
+            surface: Surface::default(),
+            material: TrackMaterial::default(),
+        }
+    }
+    pub fn validate(&self) -> Result<(), String> {
+        if self.points.len() < 3 {
+            return Err("a closed road needs at least 3 points".to_string());
+        }
+        if self.points.iter().any(|point| point.width <= 0.) {
+            return Err("every point needs a width above 0".to_string());
+        }
+        if self.subdivisions == 0 || self.texture_length <= 0. {
+            return Err("the subdivisions and texture length must be above 0".to_string());
+        }
+        Ok(())
+    }
+}
+/// Point along the middle of a road, with the directions of the road there.
+#[derive(Clone, Copy, Debug)]
+pub struct RoadSample {
+    pub translation: Vec3,
+    pub forward: Vec3,
+    /// Across the road, tilted by the banking.
+    pub right: Vec3,
+    pub up: Vec3,
+    pub width: f32,
+    /// Length of road from the start line.
+    pub distance: f32,
+}
+impl RoadSample {
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        Self {
+            translation: self.translation.lerp(other.translation, t),
+            forward: self.forward.lerp(other.forward, t).normalize_or_zero(),
+            right: self.right.lerp(other.right, t).normalize_or_zero(),
+            up: self.up.lerp(other.up, t).normalize_or_zero(),
+            width: self.width + (other.width - self.width) * t,
+            distance: self.distance + (other.distance - self.distance) * t,
+        }
+    }
+    /// Facing along the road, upright on its surface.
+    pub fn transform(&self) -> Transform {
+        Transform::from_translation(self.translation).looking_to(self.forward, self.up)
+    }
+}
+/// Road generated from `RoadSettings`, sampled along its middle. The last sample is the
+/// start line again, a whole lap further.
+#[derive(Clone, Debug)]
+pub struct Road {
+    pub samples: Vec<RoadSample>,
+    pub texture_length: f32,
+}
+impl Road {
+    pub fn generate(settings: &RoadSettings) -> Result<Self, String> {
+        settings.validate()?;
+        let points = &settings.points;
+        let count = points.len();
+        let point = |index: usize| points[index % count];
+        let mut samples: Vec<RoadSample> = Vec::new();
+        for index in 0..count {
+            let (p0, p1, p2, p3) = (point(index + count - 1), point(index), point(index + 1), point(index + 2));
+            let first = if index == 0 { 0 } else { 1 };
+            for step in first..=settings.subdivisions {
+                let t = step as f32 / settings.subdivisions as f32;
+                let translation = catmull_rom(p0.translation, p1.translation, p2.translation, p3.translation, t);
+                let tangent = catmull_rom_tangent(p0.translation, p1.translation, p2.translation, p3.translation, t);
+                let forward = tangent.normalize_or_zero();
+                let level_right = forward.cross(Vec3::Y).normalize_or_zero();
+                let banking = p1.banking + (p2.banking - p1.banking) * t;
+                let right = Quat::from_axis_angle(forward, -banking) * level_right;
+                let distance = samples
+                    .last()
+                    .map_or(0., |last| last.distance + last.translation.distance(translation));
+                samples.push(RoadSample {
+                    translation,
+                    forward,
+                    right,
+                    up: right.cross(forward),
+                    width: p1.width + (p2.width - p1.width) * t,
+                    distance,
+                });
+            }
+        }
+        Ok(Self {
+            samples,
+            texture_length: settings.texture_length,
+        })
+    }
+    /// Length of a whole lap along the middle of the road.
+    pub fn length(&self) -> f32 {
+        self.samples.last().map_or(0., |sample| sample.distance)
+    }
+    /// Middle of the road `distance` after the start line, wrapping around every lap.
+    pub fn sample_at(&self, distance: f32) -> RoadSample {
+        let length = self.length();
+        let distance = if length > 0. { distance.rem_euclid(length) } else { 0. };
+        let next = self.samples.partition_point(|sample| sample.distance < distance).clamp(1, self.samples.len() - 1);
+        let (from, to) = (&self.samples[next - 1], &self.samples[next]);
+        let span = to.distance - from.distance;
+        let t = if span > 0. { (distance - from.distance) / span } else { 0. };
+        from.lerp(to, t)
+    }
+    /// `count` checkpoints evenly along the road, the first one on the start line.
+    pub fn checkpoints(&self, count: u32) -> Vec<CheckpointSpec> {
+        (0..count)
+            .map(|index| {
+                let sample = self.sample_at(self.length() * index as f32 / count as f32);
+                let transform = sample.transform();
+                CheckpointSpec {
+                    translation: sample.translation + sample.up * CHECKPOINT_HEIGHT / 2.,
+                    rotation: transform.rotation,
+                    half_extents: Vec3::new(sample.width / 2. + CHECKPOINT_MARGIN, CHECKPOINT_HEIGHT / 2., 0.5),
+                }
+            })
+            .collect()
+    }
+    /// Just behind the start line, facing along the road.
+    pub fn spawn_point(&self) -> SpawnPoint {
+        let sample = self.sample_at(-SPAWN_BEHIND);
+        let transform = sample.transform();
+        SpawnPoint {
+            translation: sample.translation + sample.up * SPAWN_LIFT,
+            rotation: transform.rotation,
+        }
+    }
+    fn edges(&self) -> impl Iterator<Item = (Vec3, Vec3)> + '_ {
+        self.samples.iter().map(|sample| {
+            let half = sample.right * sample.width / 2.;
+            (sample.translation - half, sample.translation + half)
+        })
+    }
+    fn indices(&self) -> Vec<u32> {
+        let mut indices = Vec::with_capacity((self.samples.len() - 1) * 6);
+        for index in 0..self.samples.len() as u32 - 1 {
+            let (left, right, next_left, next_right) = (index * 2, index * 2 + 1, index * 2 + 2, index * 2 + 3);
+            indices.extend([left, right, next_left, right, next_right, next_left]);
+        }
+        indices
+    }
+    /// Road surface with the texture repeating every `texture_length`, from the left edge
+    /// across and from the start line along.
+    pub fn mesh(&self) -> Mesh {
+        let mut positions = Vec::with_capacity(self.samples.len() * 2);
+        let mut normals = Vec::with_capacity(self.samples.len() * 2);
+        let mut uvs = Vec::with_capacity(self.samples.len() * 2);
+        for (sample, (left, right)) in self.samples.iter().zip(self.edges()) {
+            let v = sample.distance / self.texture_length;
+            positions.extend([left.to_array(), right.to_array()]);
+            normals.extend([sample.up.to_array(); 2]);
+            uvs.extend([[0., v], [sample.width / self.texture_length, v]]);
+        }
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+        mesh.set_indices(Some(Indices::U32(self.indices())));
+        mesh
+    }
+    pub fn collider(&self) -> Collider {
+        let vertices = self.edges().flat_map(|(left, right)| [left, right]).collect();
+        let indices = self.indices().chunks_exact(3).map(|triangle| [triangle[0], triangle[1], triangle[2]]).collect();
+        Collider::trimesh(vertices, indices)
+    }
+}
+fn catmull_rom(p0: Vec3, p1: Vec3, p2: Vec3, p3: Vec3, t: f32) -> Vec3 {
+    let (t2, t3) = (t * t, t * t * t);
+    0.5 * (2. * p1 + (p2 - p0) * t + (2. * p0 - 5. * p1 + 4. * p2 - p3) * t2 + (3. * p1 - p0 - 3. * p2 + p3) * t3)
+}
+fn catmull_rom_tangent(p0: Vec3, p1: Vec3, p2: Vec3, p3: Vec3, t: f32) -> Vec3 {
+    let t2 = t * t;
+    0.5 * ((p2 - p0) + 2. * (2. * p0 - 5. * p1 + 4. * p2 - p3) * t + 3. * (3. * p1 - p0 - 3. * p2 + p3) * t2)
+}
@@ -0,0 +1,48 @@
+use std::path::Path;
+
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Writes `value` to `path` as pretty RON, creating the folders it goes in.
+pub fn save<T: Serialize>(value: &T, path: impl AsRef<Path>) -> Result<(), RonFileError> {
+    let text = ron::ser::to_string_pretty(value, ron::ser::PrettyConfig::default())?;
+    if let Some(parent) = path.as_ref().parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, text)?;
+    Ok(())
+}
+pub fn load<T: DeserializeOwned>(path: impl AsRef<Path>) -> Result<T, RonFileError> {
+    let bytes = std::fs::read(path)?;
+    Ok(ron::de::from_bytes(&bytes)?)
+}
+#[derive(Debug)]
+pub enum RonFileError {
+    Io(std::io::Error),
+    Serialize(ron::Error),
+    Deserialize(ron::error::SpannedError),
+}
+impl std::fmt::Display for RonFileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RonFileError::Io(err) => write!(f, "could not access the file: {err}"),
+            RonFileError::Serialize(err) => write!(f, "could not write the RON: {err}"),
+            RonFileError::Deserialize(err) => write!(f, "could not read the RON: {err}"),
+        }
+    }
+}
+impl std::error::Error for RonFileError {}
+impl From<std::io::Error> for RonFileError {
+    fn from(err: std::io::Error) -> Self {
+        RonFileError::Io(err)
+    }
+}
+impl From<ron::Error> for RonFileError {
+    fn from(err: ron::Error) -> Self {
+        RonFileError::Serialize(err)
+    }
+}
+impl From<ron::error::SpannedError> for RonFileError {
+    fn from(err: ron::error::SpannedError) -> Self {
+        RonFileError::Deserialize(err)
+    }
+}
@@ -0,0 +1,201 @@
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    render::mesh::VertexAttributeValues,
+    utils::BoxedFuture,
+};
+use bevy_rapier3d::parry::shape::FeatureId;
+use serde::Deserialize;
+
+/// What the ground under a wheel is made of. As a component it covers a whole collider.
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Deserialize)]
+pub enum Surface {
+    #[default]
+    Asphalt,
+    Sand,
+    Grass,
+    Ice,
+    Mud,
+}
+impl Surface {
+    pub const ALL: [Surface; 5] = [Surface::Asphalt, Surface::Sand, Surface::Grass, Surface::Ice, Surface::Mud];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Surface::Asphalt => "asphalt",
+            Surface::Sand => "sand",
+            Surface::Grass => "grass",
+            Surface::Ice => "ice",
+            Surface::Mud => "mud",
+        }
+    }
+    /// Surface named anywhere in `name`, like `Road_Asphalt` or `grass.001`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.to_ascii_lowercase();
+        Self::ALL.into_iter().find(|surface| name.contains(surface.name()))
+    }
+    /// Vertex color artists paint a triangle with to make it this surface: black asphalt,
+    /// yellow sand, green grass, blue ice and red mud.
+    pub fn paint(self) -> Vec3 {
+        match self {
+            Surface::Asphalt => Vec3::ZERO,
+            Surface::Sand => Vec3::new(1., 1., 0.),
+            Surface::Grass => Vec3::new(0., 1., 0.),
+            Surface::Ice => Vec3::new(0., 0., 1.),
+            Surface::Mud => Vec3::new(1., 0., 0.),
+        }
+    }
+    /// Surface painted closest to `color`.
+    pub fn from_paint(color: Vec3) -> Self {
+        Self::ALL
+            .into_iter()
+            .min_by(|a, b| a.paint().distance_squared(color).total_cmp(&b.paint().distance_squared(color)))
+            .unwrap_or_default()
+    }
+}
+/// Surface of every triangle of a trimesh collider, in the order of its triangles.
+/// Overrides the collider's `Surface`.
+#[derive(Component, Clone, Debug, Default)]
+pub struct TriangleSurfaces(pub Vec<Surface>);
+impl TriangleSurfaces {
+    /// Surfaces painted on the triangles of `mesh` with vertex colors, `None` if it has none.
+    pub fn from_vertex_colors(mesh: &Mesh) -> Option<Self> {
+        let colors: Vec<Vec3> = match mesh.attribute(Mesh::ATTRIBUTE_COLOR)? {
+            VertexAttributeValues::Float32x4(colors) => colors.iter().map(|c| Vec3::new(c[0], c[1], c[2])).collect(),
+            VertexAttributeValues::Float32x3(colors) => colors.iter().map(|c| Vec3::from(*c)).collect(),
+            _ => return None,
+        };
+        let indices: Vec<usize> = match mesh.indices() {
+            Some(indices) => indices.iter().collect(),
+            None => (0..colors.len()).collect(),
+        };
+        let surfaces = indices
+            .chunks_exact(3)
+            .map(|triangle| {
+                let color = triangle.iter().map(|index| colors[*index]).sum::<Vec3>() / 3.;
+                Surface::from_paint(color)
+            })
+            .collect();
+        Some(Self(surfaces))
+    }
+    /// Surface of the triangle a ray hit, trimeshes number the back faces after the front ones.
+    pub fn get(&self, feature: FeatureId) -> Option<Surface> {
+        let FeatureId::Face(face) = feature else {
+            return None;
+        };
+        if self.0.is_empty() {
+            return None;
+        }
+        self.0.get(face as usize % self.0.len()).copied()
+    }
+}
+/// Surface under a ray hit, from the triangle hit when the collider has them painted and
+/// asphalt when the collider doesn't say.
+pub fn surface_at(
+    surfaces: Option<&Surface>,
+    triangle_surfaces: Option<&TriangleSurfaces>,
+    feature: FeatureId,
+) -> Surface {
+    triangle_surfaces
+        .and_then(|triangles| triangles.get(feature))
+        .or(surfaces.copied())
+        .unwrap_or_default()
+}
+/// How a surface changes the tires rolling on it.
+#[derive(Clone, Debug, Deserialize)]
+pub struct SurfaceProperties {
+    /// Scales the peak of both tire curves.
+    pub grip: f32,
+    /// Added to the tire's own rolling resistance, loose ground holds the wheels back.
+    pub rolling_resistance: f32,
+    /// Linear RGB of the dust thrown up by slipping wheels, none on clean surfaces.
+    #[serde(default)]
+    pub dust: Option<Vec3>,
+    /// Looping sound of the tires sliding over the surface.
+    #[serde(default)]
+    pub skid_sound: Option<String>,
+}
+/// Properties of every surface, loaded from a `.surfaces.ron` file. The resource of the
+/// same type holds the ones in use.
+#[derive(Resource, Clone, Debug, Deserialize, TypeUuid)]
+#[uuid = "a5d3c1e8-2f47-4b96-8c0a-5e91b7d4f263"]
+pub struct SurfaceTable {
+    pub asphalt: SurfaceProperties,
+    pub sand: SurfaceProperties,
+    pub grass: SurfaceProperties,
+    pub ice: SurfaceProperties,
+    pub mud: SurfaceProperties,
+}
+impl SurfaceTable {
+    pub fn get(&self, surface: Surface) -> &SurfaceProperties {
+        match surface {
+            Surface::Asphalt => &self.asphalt,
+            Surface::Sand => &self.sand,
+            Surface::Grass => &self.grass,
+            Surface::Ice => &self.ice,
+            Surface::Mud => &self.mud,
+        }
+    }
+}
+impl Default for SurfaceTable {
+    fn default() -> Self {
+        let properties = |grip, rolling_resistance, dust| SurfaceProperties {
+            grip,
+            rolling_resistance,
+            dust,
+            skid_sound: None,
+        };
+        Self {
+            asphalt: properties(1., 0., None),
+            sand: properties(0.6, 0.08, Some(Vec3::new(0.76, 0.65, 0.45))),
+            grass: properties(0.7, 0.03, Some(Vec3::new(0.3, 0.45, 0.15))),
+            ice: properties(0.15, 0., None),
+            mud: properties(0.45, 0.15, Some(Vec3::new(0.3, 0.2, 0.1))),
+        }
+    }
+}
+#[derive(Default)]
+pub struct SurfaceTableLoader;
+impl AssetLoader for SurfaceTableLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let table: SurfaceTable = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(table));
+            Ok(())
+        })
+    }
+    fn extensions(&self) -> &[&str] {
+        &["surfaces.ron"]
+    }
+}
+#[derive(Resource)]
+pub struct SurfaceTableHandle(pub Handle<SurfaceTable>);
+
+pub fn load_surface_table(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(SurfaceTableHandle(asset_server.load("ground.surfaces.ron")));
+}
+/// Replaces the surfaces in use whenever the surfaces file is (re)loaded.
+pub fn apply_surface_table(
+    mut events: EventReader<AssetEvent<SurfaceTable>>,
+    assets: Res<Assets<SurfaceTable>>,
+    handle: Option<Res<SurfaceTableHandle>>,
+    mut table: ResMut<SurfaceTable>,
+) {
+    let Some(handle) = handle else {
+        return;
+    };
+    for event in events.iter() {
+        if let AssetEvent::Created { handle: changed } | AssetEvent::Modified { handle: changed } = event {
+            if *changed == handle.0 {
+                if let Some(loaded) = assets.get(changed) {
+                    *table = loaded.clone();
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,183 @@
+use bevy::{prelude::*, utils::HashMap};
+use bevy_rapier3d::prelude::*;
+
+use crate::{
+    car_suspension::{CarPhysics, WheelInfo},
+    surface::{Surface, SurfaceTable},
+    vehicle_input::HumanDriver,
+};
+
+/// Slip ratio or angle above which a wheel throws up dust and the tires are heard.
+const SLIP_THRESHOLD: f32 = 0.2;
+/// Slip at which the skid sound is at full volume.
+const FULL_SKID: f32 = 1.;
+/// Cars slower than this throw up no dust, however much their wheels spin.
+const DUST_MIN_SPEED: f32 = 1.;
+const DUST_INTERVAL: f32 = 0.05;
+const DUST_LIFETIME: f32 = 0.8;
+const DUST_SIZE: f32 = 0.2;
+/// How much a puff grows and rises every second.
+const DUST_GROWTH: f32 = 1.5;
+const DUST_RISE: f32 = 0.6;
+const DUST_ALPHA: f32 = 0.5;
+
+/// Dust behind wheels slipping on loose ground and the sound of the player's tires sliding,
+/// both depending on the surface under each wheel. Needs the renderer and audio.
+pub struct SurfaceEffectsPlugin;
+impl Plugin for SurfaceEffectsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<DustAssets>()
+            .init_resource::<SkidSounds>()
+            .add_systems((spawn_dust, update_dust).chain())
+            .add_system(play_skid_sounds);
+    }
+}
+#[derive(Component)]
+pub struct DustPuff {
+    pub age: f32,
+}
+#[derive(Resource)]
+struct DustAssets {
+    mesh: Option<Handle<Mesh>>,
+    materials: HashMap<Surface, Handle<StandardMaterial>>,
+    timer: Timer,
+}
+impl Default for DustAssets {
+    fn default() -> Self {
+        Self {
+            mesh: None,
+            materials: HashMap::default(),
+            timer: Timer::from_seconds(DUST_INTERVAL, TimerMode::Repeating),
+        }
+    }
+}
+/// Loops playing for each surface, with the path they were started from.
+#[derive(Resource, Default)]
+struct SkidSounds(HashMap<Surface, (String, Handle<AudioSink>)>);
+
+/// How far the wheel slips, lengthwise or sideways, zero in the air.
+pub fn wheel_slip(wheel: &WheelInfo) -> f32 {
+    if wheel.hit {
+        wheel.slip_ratio.abs().max(wheel.slip_angle.abs())
+    } else {
+        0.
+    }
+}
+fn spawn_dust(
+    mut commands: Commands,
+    time: Res<Time>,
+    table: Res<SurfaceTable>,
+    mut dust: ResMut<DustAssets>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    car_query: Query<(&CarPhysics, &Velocity)>,
+) {
+    if table.is_changed() {
+        dust.materials.clear();
+    }
+    if !dust.timer.tick(time.delta()).just_finished() {
+        return;
+    }
+    let mesh = dust
+        .mesh
+        .get_or_insert_with(|| {
+            meshes.add(Mesh::from(shape::UVSphere {
+                radius: 0.5,
+                sectors: 8,
+                stacks: 6,
+            }))
+        })
+        .clone();
+    for (car_physics, velocity) in car_query.iter() {
+        if velocity.linvel.length() < DUST_MIN_SPEED {
+            continue;
+        }
+        for wheel in car_physics.wheel_infos.iter() {
+            if wheel_slip(wheel) < SLIP_THRESHOLD {
+                continue;
+            }
+            let Some(color) = table.get(wheel.surface).dust else {
+                continue;
+            };
+            let material = dust
+                .materials
+                .entry(wheel.surface)
+                .or_insert_with(|| {
+                    materials.add(StandardMaterial {
+                        base_color: Color::rgba_linear(color.x, color.y, color.z, DUST_ALPHA),
+                        alpha_mode: AlphaMode::Blend,
+                        unlit: true,
+                        ..default()
+                    })
+                })
+                .clone();
+            commands.spawn((
+                PbrBundle {
+                    mesh: mesh.clone(),
+                    material,
+                    transform: Transform::from_translation(wheel.contact_point).with_scale(Vec3::splat(DUST_SIZE)),
+                    ..default()
+                },
+                DustPuff { age: 0. },
+            ));
+        }
+    }
+}
+/// Puffs grow and drift up until they are gone.
+fn update_dust(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut dust_query: Query<(Entity, &mut DustPuff, &mut Transform)>,
+) {
+    for (entity, mut puff, mut transform) in dust_query.iter_mut() {
+        puff.age += time.delta_seconds();
+        if puff.age >= DUST_LIFETIME {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        transform.scale = Vec3::splat(DUST_SIZE + DUST_GROWTH * puff.age);
+        transform.translation.y += DUST_RISE * time.delta_seconds();
+    }
+}
+/// Sets the loop of every surface as loud as the player's tires slide on it, silent while
+/// time stands still.
+fn play_skid_sounds(
+    time: Res<Time>,
+    audio: Res<Audio>,
+    asset_server: Res<AssetServer>,
+    table: Res<SurfaceTable>,
+    sinks: Res<Assets<AudioSink>>,
+    mut sounds: ResMut<SkidSounds>,
+    car_query: Query<&CarPhysics, With<HumanDriver>>,
+) {
+    let mut volumes: HashMap<Surface, f32> = HashMap::default();
+    if !time.is_paused() {
+        for car_physics in car_query.iter() {
+            let wheels = car_physics.wheel_infos.len().max(1) as f32;
+            for wheel in car_physics.wheel_infos.iter() {
+                let slip = wheel_slip(wheel);
+                if slip >= SLIP_THRESHOLD {
+                    *volumes.entry(wheel.surface).or_default() += (slip / FULL_SKID).min(1.) / wheels;
+                }
+            }
+        }
+    }
+    for surface in Surface::ALL {
+        let path = table.get(surface).skid_sound.as_ref();
+        let playing = sounds.0.get(&surface).map(|(playing, _)| playing);
+        if playing != path {
+            if let Some((_, sink)) = sounds.0.remove(&surface) {
+                if let Some(sink) = sinks.get(&sink) {
+                    sink.stop();
+                }
+            }
+            if let Some(path) = path {
+                let sink = audio.play_with_settings(asset_server.load(path.as_str()), PlaybackSettings::LOOP.with_volume(0.));
+                sounds.0.insert(surface, (path.clone(), sinks.get_handle(sink)));
+            }
+        }
+        if let Some(sink) = sounds.0.get(&surface).and_then(|(_, sink)| sinks.get(sink)) {
+            sink.set_volume(volumes.get(&surface).copied().unwrap_or_default().min(1.));
+        }
+    }
+}
@@ -0,0 +1,285 @@
+use bevy::{
+    prelude::*,
+    render::{mesh::Indices, render_resource::PrimitiveTopology},
+};
+use bevy_rapier3d::prelude::*;
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+use serde::Deserialize;
+
+use crate::{
+    input_replay::SimulationSeed,
+    surface::Surface,
+    track::{ActiveTrack, TrackManifest, TrackMaterial},
+};
+
+/// Hilly ground generated from noise, centered on the origin.
+#[derive(Clone, Debug, Deserialize)]
+pub struct TerrainSettings {
+    /// Same seed, same hills. Without one the hills grow from the `SimulationSeed` of the run,
+    /// new every time the track loads and the same again in its replays.
+    #[serde(default)]
+    pub seed: Option<u64>,
+    /// Width along x and depth along z.
+    pub size: Vec2,
+    /// Cells along each side, the heights are sampled at their corners.
+    pub resolution: u32,
+    /// Height between the deepest valley and the highest hill the noise can make.
+    pub height: f32,
+    /// Width of the largest hills.
+    pub feature_size: f32,
+    /// Layers of noise, each adding hills half as wide and half as high as the one before.
+    #[serde(default = "default_octaves")]
+    pub octaves: u32,
+    /// Ground around the spawn point within this distance is flat, and blends into the
+    /// hills over as much again.
+    #[serde(default = "default_flat_radius")]
+    pub flat_radius: f32,
+    #[serde(default)]
+    pub surface: Surface,
+    #[serde(default)]
+    pub material: TrackMaterial,
+}
+fn default_octaves() -> u32 {
+    4
+}
+fn default_flat_radius() -> f32 {
+    6.
+}
+impl Default for TerrainSettings {
+    fn default() -> Self {
+        Self {
+            seed: None,
+            size: Vec2::splat(500.),
+            resolution: 128,
+            height: 20.,
+            feature_size: 120.,
+            octaves: default_octaves(),
+            flat_radius: default_flat_radius(),
+            surface: Surface::default(),
+            material: TrackMaterial::default(),
+        }
+    }
+}
+impl TerrainSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.resolution == 0 {
+            return Err("the resolution must be at least 1".to_string());
+        }
+        if self.size.min_element() <= 0. || self.feature_size <= 0. {
+            return Err("the size and feature size must be above 0".to_string());
+        }
+        Ok(())
+    }
+}
+/// Heights of a generated terrain, row by row from -z to +z, each row from -x to +x.
+#[derive(Clone, Debug)]
+pub struct Terrain {
+    pub heights: Vec<f32>,
+    /// Heights along each side, one more than the cells.
+    pub samples: usize,
+    pub size: Vec2,
+}
+impl Terrain {
+    /// Terrain of `settings` from `seed`, moved up or down so the ground is flat at height
+    /// 0 around `spawn`, on the x and z axes.
+    pub fn generate(settings: &TerrainSettings, seed: u64, spawn: Vec2) -> Self {
+        let noise = Perlin::new(seed);
+        let samples = settings.resolution as usize + 1;
+        let mut terrain = Self {
+            heights: Vec::with_capacity(samples * samples),
+            samples,
+            size: settings.size,
+        };
+        let noise_height = |point: Vec2| noise.fbm(point / settings.feature_size, settings.octaves) * settings.height / 2.;
+        let spawn_height = noise_height(spawn);
+        for row in 0..samples {
+            for column in 0..samples {
+                let point = terrain.point(row, column);
+                let distance = point.distance(spawn);
+                let hills = if settings.flat_radius > 0. {
+                    let t = ((distance - settings.flat_radius) / settings.flat_radius).clamp(0., 1.);
+                    t * t * (3. - 2. * t)
+                } else {
+                    1.
+                };
+                let height = spawn_height + (noise_height(point) - spawn_height) * hills;
+                terrain.heights.push(height - spawn_height);
+            }
+        }
+        terrain
+    }
+    /// Position of a sample on the x and z axes.
+    fn point(&self, row: usize, column: usize) -> Vec2 {
+        let cells = (self.samples - 1) as f32;
+        Vec2::new(column as f32 / cells - 0.5, row as f32 / cells - 0.5) * self.size
+    }
+    fn height(&self, row: usize, column: usize) -> f32 {
+        self.heights[row * self.samples + column]
+    }
+    /// Height of the ground at `x`, `z`, clamped to the edges.
+    pub fn height_at(&self, x: f32, z: f32) -> f32 {
+        let cells = (self.samples - 1) as f32;
+        let column = ((x / self.size.x + 0.5) * cells).clamp(0., cells);
+        let row = ((z / self.size.y + 0.5) * cells).clamp(0., cells);
+        let (column0, row0) = ((column as usize).min(self.samples - 2), (row as usize).min(self.samples - 2));
+        let (u, v) = (column - column0 as f32, row - row0 as f32);
+        // the same split as the heightfield, along the diagonal from (row + 1, column) to (row, column + 1)
+        let h00 = self.height(row0, column0);
+        let h10 = self.height(row0 + 1, column0);
+        let h01 = self.height(row0, column0 + 1);
+        let h11 = self.height(row0 + 1, column0 + 1);
+        if u + v <= 1. {
+            h00 + (h01 - h00) * u + (h10 - h00) * v
+        } else {
+            h11 + (h10 - h11) * (1. - u) + (h01 - h11) * (1. - v)
+        }
+    }
+    /// Heightfield with the same triangles as `mesh`.
+    pub fn collider(&self) -> Collider {
+        // rows go along z, and rapier wants the heights column by column
+        let mut heights = Vec::with_capacity(self.heights.len());
+        for column in 0..self.samples {
+            for row in 0..self.samples {
+                heights.push(self.height(row, column));
+            }
+        }
+        Collider::heightfield(heights, self.samples, self.samples, Vec3::new(self.size.x, 1., self.size.y))
+    }
+    pub fn mesh(&self) -> Mesh {
+        let cells = self.samples - 1;
+        let cell_size = self.size / cells as f32;
+        let mut positions = Vec::with_capacity(self.heights.len());
+        let mut normals = Vec::with_capacity(self.heights.len());
+        let mut uvs = Vec::with_capacity(self.heights.len());
+        for row in 0..self.samples {
+            for column in 0..self.samples {
+                let point = self.point(row, column);
+                positions.push([point.x, self.height(row, column), point.y]);
+                let left = self.height(row, column.saturating_sub(1));
+                let right = self.height(row, (column + 1).min(cells));
+                let back = self.height(row.saturating_sub(1), column);
+                let front = self.height((row + 1).min(cells), column);
+                let normal = Vec3::new(
+                    (left - right) / (cell_size.x * 2.),
+                    1.,
+                    (back - front) / (cell_size.y * 2.),
+                )
+                .normalize();
+                normals.push(normal.to_array());
+                uvs.push([column as f32 / cells as f32, row as f32 / cells as f32]);
+            }
+        }
+        let mut indices = Vec::with_capacity(cells * cells * 6);
+        for row in 0..cells {
+            for column in 0..cells {
+                let i00 = (row * self.samples + column) as u32;
+                let i01 = i00 + 1;
+                let i10 = i00 + self.samples as u32;
+                let i11 = i10 + 1;
+                indices.extend([i00, i10, i01, i10, i11, i01]);
+            }
+        }
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+        mesh.set_indices(Some(Indices::U32(indices)));
+        mesh
+    }
+}
+/// Terrain generated for the track, with the seed its hills came from.
+#[derive(Component)]
+pub struct GeneratedTerrain {
+    pub seed: u64,
+}
+/// Hills of `settings` grown from `seed` for the track of `manifest`, flat around its spawn
+/// point, with the mesh to draw them.
+pub fn grow_terrain(manifest: &TrackManifest, settings: &TerrainSettings, seed: u64) -> (Terrain, Mesh) {
+    let spawn = manifest.spawn.translation;
+    let terrain = Terrain::generate(settings, seed, Vec2::new(spawn.x, spawn.z));
+    let mut mesh = terrain.mesh();
+    if let Err(err) = Mesh::generate_tangents(&mut mesh) {
+        warn!("Could not generate tangents for the terrain: {err}");
+    }
+    (terrain, mesh)
+}
+/// Grows the terrain again when a replay brings the seed it was recorded with, so the car
+/// drives over the same hills. Runs in the frame the replay starts, before the car's first
+/// fixed step.
+pub fn reseed_terrain(
+    seed: Res<SimulationSeed>,
+    manifests: Res<Assets<TrackManifest>>,
+    active_track: Option<Res<ActiveTrack>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut terrain_query: Query<(&mut GeneratedTerrain, &mut Handle<Mesh>, &mut Collider)>,
+) {
+    if !seed.is_changed() {
+        return;
+    }
+    let Some(manifest) = active_track.and_then(|track| manifests.get(&track.0)) else {
+        return;
+    };
+    let Some(settings) = manifest.terrain.as_ref() else {
+        return;
+    };
+    let seed = settings.seed.unwrap_or(seed.0);
+    for (mut terrain, mut mesh, mut collider) in terrain_query.iter_mut() {
+        if terrain.seed != seed {
+            let (grown, grown_mesh) = grow_terrain(manifest, settings, seed);
+            *mesh = meshes.add(grown_mesh);
+            *collider = grown.collider();
+            terrain.seed = seed;
+        }
+    }
+}
+/// Classic gradient noise, shuffled by the seed.
+struct Perlin {
+    permutation: [u8; 512],
+}
+impl Perlin {
+    fn new(seed: u64) -> Self {
+        let mut values: Vec<u8> = (0..=255).collect();
+        values.shuffle(&mut StdRng::seed_from_u64(seed));
+        let mut permutation = [0; 512];
+        for (index, value) in permutation.iter_mut().enumerate() {
+            *value = values[index % 256];
+        }
+        Self { permutation }
+    }
+    fn hash(&self, x: i32, y: i32) -> u8 {
+        let x = self.permutation[(x & 255) as usize] as usize;
+        self.permutation[x + (y & 255) as usize]
+    }
+    fn gradient(&self, x: i32, y: i32, offset: Vec2) -> f32 {
+        let angle = self.hash(x, y) as f32 / 256. * std::f32::consts::TAU;
+        Vec2::new(angle.cos(), angle.sin()).dot(offset)
+    }
+    /// Noise at `point`, roughly between -1 and 1.
+    fn noise(&self, point: Vec2) -> f32 {
+        let cell = point.floor();
+        let (x, y) = (cell.x as i32, cell.y as i32);
+        let offset = point - cell;
+        let fade = offset * offset * offset * (offset * (offset * 6. - 15.) + 10.);
+        let n00 = self.gradient(x, y, offset);
+        let n10 = self.gradient(x + 1, y, offset - Vec2::X);
+        let n01 = self.gradient(x, y + 1, offset - Vec2::Y);
+        let n11 = self.gradient(x + 1, y + 1, offset - Vec2::ONE);
+        let bottom = n00 + (n10 - n00) * fade.x;
+        let top = n01 + (n11 - n01) * fade.x;
+        (bottom + (top - bottom) * fade.y) * std::f32::consts::SQRT_2
+    }
+    /// Octaves of noise added up and scaled back to roughly between -1 and 1.
+    fn fbm(&self, point: Vec2, octaves: u32) -> f32 {
+        let mut total = 0.;
+        let mut amplitude = 1.;
+        let mut amplitudes = 0.;
+        let mut frequency = 1.;
+        for _ in 0..octaves.max(1) {
+            total += self.noise(point * frequency) * amplitude;
+            amplitudes += amplitude;
+            amplitude *= 0.5;
+            frequency *= 2.;
+        }
+        total / amplitudes
+    }
+}
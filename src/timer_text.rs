@@ -1,28 +1,129 @@
-use bevy::{diagnostic::*, prelude::*};
+use bevy::prelude::*;
+
+use crate::{
+    checkpoints::{CheckpointPassed, LapCompleted},
+    vehicle_input::HumanDriver,
+};
+
+const AHEAD_COLOR: Color = Color::rgb(0., 0.72, 0.);
+const BEHIND_COLOR: Color = Color::rgb(0.85, 0.1, 0.1);
+
 #[derive(Component)]
 pub struct TimerText
 {
     pub value : f32,
-    
+
+}
+/// Fastest lap and the time into it at each checkpoint.
+#[derive(Clone, Debug, Default)]
+pub struct LapTimes
+{
+    pub time : f32,
+    pub splits : Vec<f32>,
 }
 #[derive(Resource,Default)]
 pub struct Completion
 {
     pub started : bool,
     pub finished : bool,
+    /// Set when the player's car is respawned during the run, so the time can't be trusted.
+    pub interrupted : bool,
+    /// Seconds since the start, advanced with the physics step.
+    pub elapsed : f32,
+    pub lap_start : f32,
+    /// Time into the current lap at each checkpoint passed on it.
+    pub splits : Vec<f32>,
+    pub laps : Vec<f32>,
+    /// Fastest lap so far, kept when a race is restarted.
+    pub best_lap : Option<LapTimes>,
+    /// Last split minus the same split of the best lap, negative when ahead.
+    pub delta : Option<f32>,
+}
+impl Completion
+{
+    pub fn lap_time(&self) -> f32 {
+        self.elapsed - self.lap_start
+    }
+    /// Time of a finished run that went through without a reset and whose laps add up
+    /// to the race time, `None` for anything else.
+    pub fn valid_time(&self) -> Option<f32> {
+        let laps: f32 = self.laps.iter().sum();
+        let uninterrupted = self.started && self.finished && !self.interrupted;
+        (uninterrupted && self.elapsed > 0. && (laps - self.elapsed).abs() < 1e-3).then_some(self.elapsed)
+    }
+}
+/// Formats seconds as `mm:ss.mmm`.
+pub fn format_time(seconds: f32) -> String {
+    let millis = (seconds.max(0.) * 1000.).round() as u64;
+    format!("{:02}:{:02}.{:03}", millis / 60_000, millis / 1000 % 60, millis % 1000)
+}
+/// Formats a difference in time with its sign, like `+00:01.250`.
+pub fn format_delta(seconds: f32) -> String {
+    let sign = if seconds < 0. { '-' } else { '+' };
+    format!("{sign}{}", format_time(seconds.abs()))
+}
+pub fn advance_race_clock(fixed_time: Res<FixedTime>, mut completion: ResMut<Completion>) {
+    if completion.started && !completion.finished {
+        completion.elapsed += fixed_time.period.as_secs_f32();
+    }
+}
+/// Takes the split at every checkpoint the player passes and the time of every lap.
+pub fn record_lap_times(
+    mut checkpoint_passed: EventReader<CheckpointPassed>,
+    mut lap_completed: EventReader<LapCompleted>,
+    human_query: Query<(), With<HumanDriver>>,
+    mut completion: ResMut<Completion>,
+) {
+    for event in checkpoint_passed.iter() {
+        if !human_query.contains(event.car) {
+            continue;
+        }
+        // crossing the start line at the start is not a split, the lap began with the timer
+        if event.checkpoint == 0 && completion.splits.is_empty() && completion.laps.is_empty() {
+            continue;
+        }
+        let split = completion.lap_time();
+        let index = completion.splits.len();
+        completion.delta = completion
+            .best_lap
+            .as_ref()
+            .and_then(|best| best.splits.get(index))
+            .map(|best_split| split - best_split);
+        completion.splits.push(split);
+    }
+    for event in lap_completed.iter() {
+        if !human_query.contains(event.car) {
+            continue;
+        }
+        let lap = LapTimes {
+            time: completion.lap_time(),
+            splits: std::mem::take(&mut completion.splits),
+        };
+        completion.lap_start = completion.elapsed;
+        completion.laps.push(lap.time);
+        if completion.best_lap.as_ref().is_none_or(|best| lap.time < best.time) {
+            completion.best_lap = Some(lap);
+        }
+    }
 }
+/// Shows the race time, the current lap, the last split with its delta to the best lap,
+/// and the best lap in the sections laid out by `initialize_dialogue`.
 pub fn text_update_system(
     completion : Res<Completion>,
-    diagnostics: Res<Diagnostics>,
-    time : Res<Time>,
     mut query: Query<(&mut Text, &mut TimerText)>,
 ) {
     for (mut text,mut timer_text) in query.iter_mut() {
-        // Update the value of the second section
-        if completion.started && !completion.finished
-        {
-            timer_text.value+=time.delta_seconds();
-            text.sections[1].value = timer_text.value.to_string();
+        timer_text.value = completion.elapsed;
+        text.sections[1].value = format_time(completion.elapsed);
+        text.sections[3].value = format_time(completion.lap_time());
+        text.sections[5].value = completion.splits.last().map(|split| format_time(*split)).unwrap_or_default();
+        match completion.delta {
+            Some(delta) => {
+                text.sections[6].value = format!(" {}", format_delta(delta));
+                text.sections[6].style.color = if delta <= 0. { AHEAD_COLOR } else { BEHIND_COLOR };
+            }
+            None => text.sections[6].value.clear(),
         }
+        text.sections[8].value = completion.best_lap.as_ref().map(|best| format_time(best.time)).unwrap_or_default();
     }
 }
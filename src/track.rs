@@ -0,0 +1,256 @@
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    utils::BoxedFuture,
+};
+use serde::Deserialize;
+
+use crate::{
+    car_respawn::RespawnSettings, checkpoints::CheckpointSpec, race_state::StartTransform, surface::Surface,
+    road::{Road, RoadSettings}, terrain::TerrainSettings, track_scene::TrackScene,
+};
+
+/// Folder searched for `.track.ron` manifests, relative to the assets.
+pub const TRACKS_FOLDER: &str = "tracks";
+
+/// Everything about a track, loaded from a `.track.ron` manifest so tracks can be added
+/// without touching the code. Paths are relative to the assets folder.
+#[derive(Clone, Debug, Deserialize, TypeUuid)]
+#[uuid = "3e8b7f2c-9a41-4d6e-b5c0-71f2d8a4e913"]
+pub struct TrackManifest {
+    /// Shown on the selection screen and the leaderboard, which keeps separate times for
+    /// each track.
+    pub name: String,
+    /// Meshes making up the ground, each gets a collider of its exact shape.
+    #[serde(default)]
+    pub meshes: Vec<TrackMesh>,
+    /// Whole glTF scenes with their own materials, see `track_scene` for how their nodes
+    /// get colliders.
+    #[serde(default)]
+    pub scenes: Vec<TrackScene>,
+    /// Hills generated from noise, for driving off-road.
+    #[serde(default)]
+    pub terrain: Option<TerrainSettings>,
+    /// Road generated along a spline, which can also place the checkpoints and spawn point.
+    #[serde(default)]
+    pub road: Option<RoadSettings>,
+    /// Where the player's car starts.
+    #[serde(default)]
+    pub spawn: SpawnPoint,
+    /// Checkpoints in the order they have to be passed, the first one is the start line.
+    /// Tracks without any are for driving around freely.
+    #[serde(default)]
+    pub checkpoints: Vec<CheckpointSpec>,
+    /// Line to cross last on tracks that aren't circuits, without it the last checkpoint
+    /// is the finish.
+    #[serde(default)]
+    pub finish: Option<CheckpointSpec>,
+    /// On a circuit the start line is also the finish line and the race lasts `laps`
+    /// laps, otherwise the race ends at the finish.
+    #[serde(default)]
+    pub circuit: bool,
+    #[serde(default = "default_laps")]
+    pub laps: u32,
+    /// Cars falling below this height are put back on the track.
+    #[serde(default = "default_kill_height")]
+    pub kill_height: f32,
+    #[serde(default)]
+    pub lighting: TrackLighting,
+}
+fn default_laps() -> u32 {
+    1
+}
+fn default_kill_height() -> f32 {
+    RespawnSettings::default().kill_height
+}
+impl Default for TrackManifest {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            meshes: Vec::new(),
+            scenes: Vec::new(),
+            terrain: None,
+            road: None,
+            spawn: default(),
+            checkpoints: Vec::new(),
+            finish: None,
+            circuit: false,
+            laps: default_laps(),
+            kill_height: default_kill_height(),
+            lighting: default(),
+        }
+    }
+}
+impl TrackManifest {
+    /// Circuit around `road`, with the checkpoints and spawn point it places.
+    pub fn from_road(name: impl Into<String>, road: RoadSettings) -> Result<Self, String> {
+        let mut manifest = Self {
+            name: name.into(),
+            circuit: true,
+            road: Some(road),
+            ..default()
+        };
+        manifest.place_on_road()?;
+        Ok(manifest)
+    }
+    /// Takes the checkpoints and spawn point from the road, when it is asked to place them.
+    pub fn place_on_road(&mut self) -> Result<(), String> {
+        let Some(settings) = self.road.as_ref() else {
+            return Ok(());
+        };
+        let road = Road::generate(settings)?;
+        if settings.checkpoints > 0 {
+            self.checkpoints = road.checkpoints(settings.checkpoints);
+        }
+        if settings.place_spawn {
+            self.spawn = road.spawn_point();
+        }
+        Ok(())
+    }
+    /// Refuses layouts the race can't be finished on, the loader fails on these.
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.circuit && self.gates().count() == 1 {
+            return Err("a race that isn't a circuit needs a finish line after its start line".to_string());
+        }
+        Ok(())
+    }
+    /// Checkpoints followed by the finish line, in the order they have to be passed.
+    pub fn gates(&self) -> impl Iterator<Item = &CheckpointSpec> {
+        let finish = self.finish.as_ref().filter(|_| !self.circuit);
+        self.checkpoints.iter().chain(finish)
+    }
+}
+#[derive(Clone, Debug, Deserialize)]
+pub struct TrackMesh {
+    /// Labeled mesh in a model, like `racetrack.glb#Mesh0/Primitive0`.
+    pub mesh: String,
+    #[serde(default)]
+    pub material: TrackMaterial,
+    /// What the wheels drive on, unless `painted`.
+    #[serde(default)]
+    pub surface: Surface,
+    /// Take the surface of each triangle from the vertex colors, see `Surface::paint`.
+    #[serde(default)]
+    pub painted: bool,
+}
+#[derive(Clone, Debug, Deserialize)]
+pub struct TrackMaterial {
+    /// Linear RGB, may go above 1 to brighten the texture.
+    pub base_color: Vec3,
+    #[serde(default)]
+    pub base_color_texture: Option<String>,
+    #[serde(default)]
+    pub normal_map_texture: Option<String>,
+    pub perceptual_roughness: f32,
+}
+impl Default for TrackMaterial {
+    fn default() -> Self {
+        Self {
+            base_color: Vec3::ONE,
+            base_color_texture: None,
+            normal_map_texture: None,
+            perceptual_roughness: 0.5,
+        }
+    }
+}
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+pub struct SpawnPoint {
+    pub translation: Vec3,
+    #[serde(default)]
+    pub rotation: Quat,
+}
+impl SpawnPoint {
+    pub fn transform(&self) -> Transform {
+        Transform::from_translation(self.translation).with_rotation(self.rotation)
+    }
+}
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct TrackLighting {
+    pub ambient_color: Vec3,
+    pub ambient_brightness: f32,
+    pub sun_illuminance: f32,
+    /// Direction the sun shines in, turned from straight down the -Z axis.
+    pub sun_rotation: Quat,
+    pub shadows: bool,
+}
+impl Default for TrackLighting {
+    fn default() -> Self {
+        Self {
+            ambient_color: Vec3::ONE,
+            ambient_brightness: 0.3,
+            sun_illuminance: 10000.,
+            sun_rotation: Quat::from_rotation_x(-std::f32::consts::PI / 4.),
+            shadows: true,
+        }
+    }
+}
+#[derive(Default)]
+pub struct TrackManifestLoader;
+impl AssetLoader for TrackManifestLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let mut manifest: TrackManifest = ron::de::from_bytes(bytes)?;
+            manifest
+                .validate()
+                .map_err(|err| bevy::asset::Error::msg(format!("{} can't be raced: {err}", manifest.name)))?;
+            // a broken road is reported when the track loads
+            if let Err(err) = manifest.place_on_road() {
+                warn!("Could not place {} on its road: {err}", manifest.name);
+            }
+            load_context.set_default_asset(LoadedAsset::new(manifest));
+            Ok(())
+        })
+    }
+    fn extensions(&self) -> &[&str] {
+        &["track.ron"]
+    }
+}
+/// Track being raced on.
+#[derive(Resource)]
+pub struct ActiveTrack(pub Handle<TrackManifest>);
+/// Every manifest found in `TRACKS_FOLDER`, offered on the selection screen.
+#[derive(Resource, Default)]
+pub struct TrackList(pub Vec<Handle<TrackManifest>>);
+
+pub fn load_track_list(asset_server: Res<AssetServer>, mut track_list: ResMut<TrackList>) {
+    match asset_server.load_folder(TRACKS_FOLDER) {
+        Ok(handles) => track_list.0 = handles.into_iter().map(|handle| handle.typed()).collect(),
+        Err(err) => error!("Could not list the tracks in {TRACKS_FOLDER}: {err}"),
+    }
+}
+/// Whether the active track was just picked, or its manifest just (re)loaded.
+pub fn active_track_changed(
+    events: &mut EventReader<AssetEvent<TrackManifest>>,
+    active_track: &Res<ActiveTrack>,
+) -> bool {
+    let reloaded = events.iter().any(|event| match event {
+        AssetEvent::Created { handle } | AssetEvent::Modified { handle } => *handle == active_track.0,
+        AssetEvent::Removed { .. } => false,
+    });
+    reloaded || active_track.is_changed()
+}
+/// Takes the start and the kill plane from the active track.
+pub fn apply_track_settings(
+    mut events: EventReader<AssetEvent<TrackManifest>>,
+    manifests: Res<Assets<TrackManifest>>,
+    active_track: Option<Res<ActiveTrack>>,
+    mut start: ResMut<StartTransform>,
+    mut respawn_settings: ResMut<RespawnSettings>,
+) {
+    let Some(active_track) = active_track else {
+        return;
+    };
+    if !active_track_changed(&mut events, &active_track) {
+        return;
+    }
+    let Some(manifest) = manifests.get(&active_track.0) else {
+        return;
+    };
+    start.0 = manifest.spawn.transform();
+    respawn_settings.kill_height = manifest.kill_height;
+}
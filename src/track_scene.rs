@@ -0,0 +1,163 @@
+use bevy::{gltf::GltfExtras, prelude::*, scene::SceneInstance};
+use bevy_rapier3d::prelude::*;
+use serde::Deserialize;
+
+use crate::surface::{Surface, TriangleSurfaces};
+
+/// Custom property of a glTF node picking its collider, `"trimesh"`, `"convex"` or `"none"`.
+pub const COLLIDER_PROPERTY: &str = "collider";
+/// Custom property of a glTF node naming its surface, or `"painted"` to take the surface of
+/// each triangle from the vertex colors. Without it the surface is looked for in the name.
+pub const SURFACE_PROPERTY: &str = "surface";
+const PAINTED: &str = "painted";
+/// Nodes named like this get a convex decomposition when they have no `collider` property.
+pub const PROP_PREFIX: &str = "prop";
+/// Nodes named like this get no collider when they have no `collider` property.
+pub const DECORATION_PREFIX: &str = "deco";
+/// Voxels along the longest side of a prop when splitting it into convex pieces, props
+/// are small enough to do with less than the default 64.
+const PROP_RESOLUTION: u32 = 32;
+
+/// glTF scene making up part of a track, like `forest.glb#Scene0`.
+#[derive(Clone, Debug, Deserialize)]
+pub struct TrackScene {
+    pub scene: String,
+    #[serde(default)]
+    pub translation: Vec3,
+    #[serde(default)]
+    pub rotation: Quat,
+}
+impl TrackScene {
+    pub fn transform(&self) -> Transform {
+        Transform::from_translation(self.translation).with_rotation(self.rotation)
+    }
+}
+/// How the meshes of a scene node collide.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ColliderKind {
+    /// Exact shape, for the road and anything else driven on.
+    #[default]
+    TriMesh,
+    /// Convex pieces, for props the cars can bump into.
+    Convex,
+    /// Only seen, never touched.
+    None,
+}
+impl ColliderKind {
+    fn from_property(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "trimesh" => Some(Self::TriMesh),
+            "convex" => Some(Self::Convex),
+            "none" => Some(Self::None),
+            _ => None,
+        }
+    }
+    /// Kind set by the `collider` property in the node's extras, or else guessed from its name.
+    pub fn for_node(name: &str, extras: Option<&str>) -> Self {
+        if let Some(property) = node_property(extras, COLLIDER_PROPERTY) {
+            match Self::from_property(&property) {
+                Some(kind) => return kind,
+                None => warn!("Node {name} has an unknown collider \"{property}\""),
+            }
+        }
+        let name = name.to_ascii_lowercase();
+        if name.starts_with(PROP_PREFIX) {
+            Self::Convex
+        } else if name.starts_with(DECORATION_PREFIX) {
+            Self::None
+        } else {
+            Self::TriMesh
+        }
+    }
+    pub fn shape(self) -> Option<ComputedColliderShape> {
+        match self {
+            Self::TriMesh => Some(ComputedColliderShape::TriMesh),
+            Self::Convex => Some(ComputedColliderShape::ConvexDecomposition(VHACDParameters {
+                resolution: PROP_RESOLUTION,
+                ..default()
+            })),
+            Self::None => None,
+        }
+    }
+}
+/// Custom property `key` of a node, from the JSON glTF keeps them in.
+fn node_property(extras: Option<&str>, key: &str) -> Option<String> {
+    let extras: serde_json::Value = serde_json::from_str(extras?).ok()?;
+    extras.get(key)?.as_str().map(str::to_string)
+}
+/// Surface components of the collider built from `mesh` on node `name`, nothing for asphalt.
+pub fn node_surface(name: &str, extras: Option<&str>, mesh: &Mesh) -> (Option<Surface>, Option<TriangleSurfaces>) {
+    let Some(property) = node_property(extras, SURFACE_PROPERTY) else {
+        return (Surface::from_name(name), None);
+    };
+    if property.eq_ignore_ascii_case(PAINTED) {
+        let triangles = TriangleSurfaces::from_vertex_colors(mesh);
+        if triangles.is_none() {
+            warn!("Node {name} is painted but its mesh has no vertex colors");
+        }
+        return (None, triangles);
+    }
+    let surface = Surface::from_name(&property);
+    if surface.is_none() {
+        warn!("Node {name} has an unknown surface \"{property}\"");
+    }
+    (surface, None)
+}
+/// Scene whose meshes still need their colliders, removed once they have them.
+#[derive(Component)]
+pub struct SceneColliders;
+
+/// Gives every mesh of a spawned track scene the collider and surface of its node. glTF
+/// puts each primitive in a child of the node, so the node is the parent of the mesh entity.
+pub fn build_scene_colliders(
+    mut commands: Commands,
+    meshes: Res<Assets<Mesh>>,
+    scene_spawner: Res<SceneSpawner>,
+    scene_query: Query<(Entity, &SceneInstance), With<SceneColliders>>,
+    children: Query<&Children>,
+    mesh_query: Query<(&Handle<Mesh>, Option<&Parent>)>,
+    node_query: Query<(Option<&Name>, Option<&GltfExtras>)>,
+) {
+    for (scene_entity, instance) in scene_query.iter() {
+        if !scene_spawner.instance_is_ready(**instance) {
+            continue;
+        }
+        for entity in children.iter_descendants(scene_entity) {
+            let Ok((handle, parent)) = mesh_query.get(entity) else {
+                continue;
+            };
+            let node = parent.map_or(entity, |parent| parent.get());
+            let (name, extras) = node_query.get(node).unwrap_or_default();
+            let name = name.map_or("", |name| name.as_str());
+            let extras = extras.map(|extras| extras.value.as_str());
+            let kind = ColliderKind::for_node(name, extras);
+            let Some(shape) = kind.shape() else {
+                continue;
+            };
+            let Some(mesh) = meshes.get(handle) else {
+                error!("Could not build a collider for node {name}, its mesh is missing");
+                continue;
+            };
+            let Some(collider) = Collider::from_bevy_mesh(mesh, &shape) else {
+                error!("Could not build a collider for node {name}");
+                continue;
+            };
+            let mut mesh_entity = commands.entity(entity);
+            mesh_entity.insert(collider);
+            match node_surface(name, extras, mesh) {
+                // only a trimesh keeps the triangles of the mesh the surfaces are looked up by
+                (_, Some(triangles)) if kind == ColliderKind::TriMesh => {
+                    mesh_entity.insert(triangles);
+                }
+                (_, Some(_)) => {
+                    warn!("Node {name} is painted but its collider is {kind:?}, only a trimesh can be painted");
+                }
+                (Some(surface), None) => {
+                    mesh_entity.insert(surface);
+                }
+                (None, None) => {}
+            }
+        }
+        commands.entity(scene_entity).remove::<SceneColliders>();
+    }
+}
@@ -1,6 +1,56 @@
 use bevy::{prelude::*, window::PrimaryWindow};
 
-use crate::timer_text::TimerText;
+use crate::{
+    leaderboard::{format_date, Leaderboard, RaceResult},
+    loading::{LoadFailure, LoadingProgress},
+    race_state::{Countdown, RaceState},
+    timer_text::{format_time, Completion, TimerText},
+    track::{ActiveTrack, TrackList, TrackManifest},
+};
+
+/// Number of times listed on the results screen.
+const RESULTS_SHOWN: usize = 10;
+const HIGHLIGHT_COLOR: Color = Color::rgb(1., 0.8, 0.2);
+const TRACK_BUTTON_COLOR: Color = Color::rgb(0.25, 0.25, 0.25);
+const TRACK_BUTTON_HOVER_COLOR: Color = Color::rgb(0.35, 0.35, 0.35);
+/// Shortcuts for the first tracks on the selection screen.
+const TRACK_KEYS: [KeyCode; 9] = [
+    KeyCode::Key1,
+    KeyCode::Key2,
+    KeyCode::Key3,
+    KeyCode::Key4,
+    KeyCode::Key5,
+    KeyCode::Key6,
+    KeyCode::Key7,
+    KeyCode::Key8,
+    KeyCode::Key9,
+];
+
+/// How long GO stays up once the race has started, in seconds.
+const GO_SHOWN: f32 = 1.;
+
+#[derive(Component)]
+pub struct ResultsScreen;
+/// Covers the screen while the track loads.
+#[derive(Component)]
+pub struct LoadingScreen;
+#[derive(Component)]
+pub struct LoadingText;
+#[derive(Component)]
+pub struct LoadingBar;
+/// Shown when the race could not be loaded.
+#[derive(Component)]
+pub struct LoadErrorScreen;
+/// Lists the tracks to pick from.
+#[derive(Component)]
+pub struct TrackSelectScreen;
+#[derive(Component)]
+pub struct TrackButtons;
+#[derive(Component)]
+pub struct TrackButton(pub Handle<TrackManifest>);
+/// Large text in the middle of the screen for the countdown and the pause.
+#[derive(Component)]
+pub struct RaceStateText;
 
 pub fn initialize_fps_text(_commands: Commands, _asset_server: Res<AssetServer>) {
 }
@@ -8,10 +58,10 @@ pub fn initialize_dialogue(asset_server: Res<AssetServer>,
 mut commands: Commands,
 primary_query: Query<&Window, With<PrimaryWindow>>)
 {
-    let Ok(primary) = primary_query.get_single() else
-    {
+    // nothing to show the timer on without a window
+    if primary_query.get_single().is_err() {
         return;
-    };
+    }
     commands.spawn(NodeBundle {
         style: Style {
             size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
@@ -45,32 +95,16 @@ primary_query: Query<&Window, With<PrimaryWindow>>)
                     text: Text {
                         // Construct a `Vec` of `TextSection`s
                         sections: vec![
-                            TextSection {
-                                value: "Timer: ".to_string(),
-                                style: TextStyle {
-                                    font:asset_server.load("lato.regular.ttf"),
-                                    font_size: 30.0,
-                                    color: Color::Rgba {
-                                        red: 0.,
-                                        green: 0.7215686275,
-                                        blue: 0.,
-                                        alpha: 1.0,
-                                    },
-                                },
-                            },
-                            TextSection {
-                                value: "".to_string(),
-                                style: TextStyle {
-                                    font:asset_server.load("lato.regular.ttf"),
-                                    font_size: 30.0,
-                                    color: Color::Rgba {
-                                        red: 0.,
-                                        green: 0.7215686275,
-                                        blue: 0.,
-                                        alpha: 1.0,
-                                    },
-                                },
-                            },
+                            timer_section("Timer: ", &asset_server),
+                            timer_section("", &asset_server),
+                            timer_section("   Lap: ", &asset_server),
+                            timer_section("", &asset_server),
+                            timer_section("   Split: ", &asset_server),
+                            timer_section("", &asset_server),
+                            // delta to the best lap, colored by `text_update_system`
+                            timer_section("", &asset_server),
+                            timer_section("   Best: ", &asset_server),
+                            timer_section("", &asset_server),
                         ],
                         ..default()
                     },
@@ -80,4 +114,398 @@ primary_query: Query<&Window, With<PrimaryWindow>>)
                 
             });
     });
-}
\ No newline at end of file
+}
+fn timer_section(value: &str, asset_server: &AssetServer) -> TextSection {
+    TextSection {
+        value: value.to_string(),
+        style: TextStyle {
+            font:asset_server.load("lato.regular.ttf"),
+            font_size: 30.0,
+            color: Color::rgb_u8(0, 184, 0),
+        },
+    }
+}
+/// Shows the player's time and the best times of the track and car once a race is over.
+pub fn show_results_screen(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    result: Res<RaceResult>,
+    leaderboard: Res<Leaderboard>,
+    screen_query: Query<Entity, With<ResultsScreen>>,
+) {
+    if !result.is_changed() {
+        return;
+    }
+    for entity in screen_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    let Some(run) = result.0.as_ref() else {
+        return;
+    };
+    let title = match run.place {
+        Some(place) => format!("Finished in {}, place {place}", format_time(run.entry.time)),
+        None => format!("Finished in {}, not counted", format_time(run.entry.time)),
+    };
+    let mut lines = vec![
+        (title, HIGHLIGHT_COLOR),
+        (format!("{} - {}", run.entry.track, run.entry.car), Color::WHITE),
+    ];
+    for (index, entry) in leaderboard.top(&run.entry.track, &run.entry.car, RESULTS_SHOWN).into_iter().enumerate() {
+        let color = if *entry == run.entry { HIGHLIGHT_COLOR } else { Color::WHITE };
+        let line = format!("{:>2}. {}   {}   {}", index + 1, format_time(entry.time), entry.player, format_date(entry.date));
+        lines.push((line, color));
+    }
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                    flex_direction: FlexDirection::Column,
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                background_color: Color::rgba(0., 0., 0., 0.6).into(),
+                ..default()
+            },
+            ResultsScreen,
+        ))
+        .with_children(|parent| {
+            for (line, color) in lines {
+                parent.spawn(TextBundle::from_section(
+                    line,
+                    TextStyle {
+                        font: asset_server.load("lato.regular.ttf"),
+                        font_size: 30.0,
+                        color,
+                    },
+                ));
+            }
+        });
+}
+pub fn initialize_race_state_text(asset_server: Res<AssetServer>, mut commands: Commands) {
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font: asset_server.load("lato.regular.ttf"),
+                        font_size: 120.0,
+                        color: HIGHLIGHT_COLOR,
+                    },
+                ),
+                RaceStateText,
+            ));
+        });
+}
+/// Counts down 3-2-1-GO before the race and says when it is paused.
+pub fn race_state_text_update(
+    state: Res<State<RaceState>>,
+    countdown: Option<Res<Countdown>>,
+    completion: Res<Completion>,
+    mut text_query: Query<&mut Text, With<RaceStateText>>,
+) {
+    let value = match state.0 {
+        RaceState::Countdown => countdown
+            .map(|countdown| format!("{}", countdown.remaining.ceil().max(1.)))
+            .unwrap_or_default(),
+        RaceState::Racing if completion.elapsed < GO_SHOWN => "GO!".to_string(),
+        RaceState::Paused => "Paused".to_string(),
+        _ => String::new(),
+    };
+    for mut text in text_query.iter_mut() {
+        if text.sections[0].value != value {
+            text.sections[0].value = value.clone();
+        }
+    }
+}
+pub fn initialize_loading_screen(asset_server: Res<AssetServer>, mut commands: Commands) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                    flex_direction: FlexDirection::Column,
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                background_color: Color::rgba(0.15, 0.15, 0.15, 1.).into(),
+                ..default()
+            },
+            LoadingScreen,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section(
+                    "Loading",
+                    TextStyle {
+                        font: asset_server.load("lato.regular.ttf"),
+                        font_size: 40.0,
+                        color: Color::WHITE,
+                    },
+                ),
+                LoadingText,
+            ));
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Percent(40.0), Val::Px(12.)),
+                        margin: UiRect::top(Val::Px(20.)),
+                        ..default()
+                    },
+                    background_color: Color::rgba(0.3, 0.3, 0.3, 1.).into(),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent.spawn((
+                        NodeBundle {
+                            style: Style {
+                                size: Size::new(Val::Percent(0.0), Val::Percent(100.0)),
+                                ..default()
+                            },
+                            background_color: HIGHLIGHT_COLOR.into(),
+                            ..default()
+                        },
+                        LoadingBar,
+                    ));
+                });
+        });
+}
+pub fn update_loading_screen(
+    progress: Res<LoadingProgress>,
+    mut text_query: Query<&mut Text, With<LoadingText>>,
+    mut bar_query: Query<&mut Style, With<LoadingBar>>,
+) {
+    if !progress.is_changed() {
+        return;
+    }
+    let fraction = if progress.total == 0 { 0. } else { progress.loaded as f32 / progress.total as f32 };
+    for mut text in text_query.iter_mut() {
+        text.sections[0].value = format!("Loading {}/{}", progress.loaded, progress.total);
+    }
+    for mut style in bar_query.iter_mut() {
+        style.size.width = Val::Percent(fraction * 100.);
+    }
+}
+pub fn despawn_loading_screen(mut commands: Commands, screen_query: Query<Entity, With<LoadingScreen>>) {
+    for entity in screen_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+/// Tells the player which asset kept the race from loading.
+pub fn show_load_error(asset_server: Res<AssetServer>, failure: Res<LoadFailure>, mut commands: Commands) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                    flex_direction: FlexDirection::Column,
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                background_color: Color::rgba(0.15, 0.15, 0.15, 1.).into(),
+                ..default()
+            },
+            LoadErrorScreen,
+        ))
+        .with_children(|parent| {
+            let lines = [
+                (format!("Could not load {}", failure.asset), 40.0, Color::rgb(0.85, 0.1, 0.1)),
+                (failure.reason.clone(), 30.0, Color::WHITE),
+                ("Press Escape to pick another track".to_string(), 24.0, Color::GRAY),
+            ];
+            for (line, font_size, color) in lines {
+                parent.spawn(TextBundle::from_section(
+                    line,
+                    TextStyle {
+                        font: asset_server.load("lato.regular.ttf"),
+                        font_size,
+                        color,
+                    },
+                ));
+            }
+        });
+}
+pub fn despawn_load_error(mut commands: Commands, screen_query: Query<Entity, With<LoadErrorScreen>>) {
+    for entity in screen_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+/// Tracks whose manifest has loaded, sorted by name so the number keys stay put.
+fn loaded_tracks<'a>(
+    track_list: &TrackList,
+    manifests: &'a Assets<TrackManifest>,
+) -> Vec<(Handle<TrackManifest>, &'a TrackManifest)> {
+    let mut tracks: Vec<_> = track_list
+        .0
+        .iter()
+        .filter_map(|handle| manifests.get(handle).map(|manifest| (handle.clone(), manifest)))
+        .collect();
+    tracks.sort_by(|a, b| a.1.name.cmp(&b.1.name));
+    tracks
+}
+fn spawn_track_buttons(
+    parent: &mut ChildBuilder,
+    asset_server: &AssetServer,
+    tracks: Vec<(Handle<TrackManifest>, &TrackManifest)>,
+) {
+    if tracks.is_empty() {
+        parent.spawn(TextBundle::from_section(
+            "No tracks found",
+            TextStyle {
+                font: asset_server.load("lato.regular.ttf"),
+                font_size: 30.0,
+                color: Color::GRAY,
+            },
+        ));
+    }
+    for (index, (handle, manifest)) in tracks.into_iter().enumerate() {
+        let kind = if manifest.checkpoints.is_empty() {
+            "free drive".to_string()
+        } else if manifest.circuit {
+            format!("{} laps", manifest.laps)
+        } else {
+            "sprint".to_string()
+        };
+        parent
+            .spawn((
+                ButtonBundle {
+                    style: Style {
+                        size: Size::new(Val::Px(400.), Val::Px(50.)),
+                        margin: UiRect::all(Val::Px(5.)),
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..default()
+                    },
+                    background_color: TRACK_BUTTON_COLOR.into(),
+                    ..default()
+                },
+                TrackButton(handle),
+            ))
+            .with_children(|parent| {
+                parent.spawn(TextBundle::from_section(
+                    format!("{}. {} ({kind})", index + 1, manifest.name),
+                    TextStyle {
+                        font: asset_server.load("lato.regular.ttf"),
+                        font_size: 30.0,
+                        color: Color::WHITE,
+                    },
+                ));
+            });
+    }
+}
+pub fn initialize_track_select(
+    asset_server: Res<AssetServer>,
+    track_list: Res<TrackList>,
+    manifests: Res<Assets<TrackManifest>>,
+    mut commands: Commands,
+) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                    flex_direction: FlexDirection::Column,
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                background_color: Color::rgba(0.15, 0.15, 0.15, 1.).into(),
+                ..default()
+            },
+            TrackSelectScreen,
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                "Pick a track",
+                TextStyle {
+                    font: asset_server.load("lato.regular.ttf"),
+                    font_size: 40.0,
+                    color: HIGHLIGHT_COLOR,
+                },
+            ));
+            parent
+                .spawn((
+                    NodeBundle {
+                        style: Style {
+                            flex_direction: FlexDirection::Column,
+                            align_items: AlignItems::Center,
+                            margin: UiRect::top(Val::Px(20.)),
+                            ..default()
+                        },
+                        ..default()
+                    },
+                    TrackButtons,
+                ))
+                .with_children(|parent| {
+                    spawn_track_buttons(parent, &asset_server, loaded_tracks(&track_list, &manifests));
+                });
+        });
+}
+/// Lists the tracks again as their manifests come in, and starts loading the one picked
+/// with the mouse or the number keys.
+#[allow(clippy::too_many_arguments)]
+pub fn update_track_select(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    keys: Res<Input<KeyCode>>,
+    track_list: Res<TrackList>,
+    manifests: Res<Assets<TrackManifest>>,
+    mut manifest_events: EventReader<AssetEvent<TrackManifest>>,
+    list_query: Query<Entity, With<TrackButtons>>,
+    mut button_query: Query<(&Interaction, &TrackButton, &mut BackgroundColor), Changed<Interaction>>,
+    mut next_state: ResMut<NextState<RaceState>>,
+) {
+    if manifest_events.iter().count() > 0 || track_list.is_changed() {
+        for entity in list_query.iter() {
+            let mut list = commands.entity(entity);
+            list.despawn_descendants();
+            list.with_children(|parent| {
+                spawn_track_buttons(parent, &asset_server, loaded_tracks(&track_list, &manifests));
+            });
+        }
+    }
+    let mut picked = None;
+    for (interaction, button, mut color) in button_query.iter_mut() {
+        *color = match interaction {
+            Interaction::Clicked => {
+                picked = Some(button.0.clone());
+                HIGHLIGHT_COLOR.into()
+            }
+            Interaction::Hovered => TRACK_BUTTON_HOVER_COLOR.into(),
+            Interaction::None => TRACK_BUTTON_COLOR.into(),
+        };
+    }
+    let tracks = loaded_tracks(&track_list, &manifests);
+    for (key, (handle, _)) in TRACK_KEYS.iter().zip(tracks) {
+        if keys.just_pressed(*key) {
+            picked = Some(handle);
+        }
+    }
+    if let Some(handle) = picked {
+        commands.insert_resource(ActiveTrack(handle));
+        next_state.set(RaceState::Loading);
+    }
+}
+pub fn despawn_track_select(mut commands: Commands, screen_query: Query<Entity, With<TrackSelectScreen>>) {
+    for entity in screen_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
@@ -6,5 +6,11 @@ pub fn move_towards(current: Vec3, target: Vec3, max_dist_delta: f32) -> Vec3 {
     if magnitude <= max_dist_delta || magnitude == 0. {
         return target;
     }
-    return current + a / magnitude * max_dist_delta;
+    current + a / magnitude * max_dist_delta
+}
+pub fn move_towards_f32(current: f32, target: f32, max_delta: f32) -> f32 {
+    if (target - current).abs() <= max_delta {
+        return target;
+    }
+    current + (target - current).signum() * max_delta
 }
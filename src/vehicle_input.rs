@@ -0,0 +1,382 @@
+use std::path::{Path, PathBuf};
+
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    utils::BoxedFuture,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::ron_file::{self, RonFileError};
+
+/// Where the controls rebound in game are kept between sessions.
+pub const BINDINGS_PATH: &str = "saves/input.bindings.ron";
+
+/// What the driver wants the car to do, filled in by the keyboard and gamepad systems
+/// and read by `car_controls`.
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct VehicleInput {
+    /// Gas pedal, from `0` to `1`.
+    pub throttle: f32,
+    /// Brake pedal, from `0` to `1`.
+    pub brake: f32,
+    /// `-1` is full lock to the left and `1` full lock to the right.
+    pub steer: f32,
+    pub handbrake: bool,
+    /// The flags below are set on a button press and cleared by the fixed step that acts
+    /// on them, so a press is neither missed nor repeated between physics steps.
+    pub reset: bool,
+    pub shift_up: bool,
+    pub shift_down: bool,
+    pub toggle_automatic: bool,
+}
+/// Marks a car whose `VehicleInput` comes from the keyboard and a gamepad, as opposed to
+/// one driven by an AI or a replay.
+#[derive(Component, Clone, Copy, Debug, Default)]
+pub struct HumanDriver {
+    /// Gamepad driving the car, handed out by `assign_gamepads`.
+    pub gamepad: Option<Gamepad>,
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+pub enum InputAction {
+    Throttle,
+    Brake,
+    SteerLeft,
+    SteerRight,
+    Handbrake,
+    Reset,
+    ShiftUp,
+    ShiftDown,
+    ToggleAutomatic,
+}
+impl InputAction {
+    /// Order the actions are asked for while rebinding.
+    pub const ALL: [InputAction; 9] = [
+        InputAction::Throttle,
+        InputAction::Brake,
+        InputAction::SteerLeft,
+        InputAction::SteerRight,
+        InputAction::Handbrake,
+        InputAction::Reset,
+        InputAction::ShiftUp,
+        InputAction::ShiftDown,
+        InputAction::ToggleAutomatic,
+    ];
+}
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct KeyboardBindings {
+    pub throttle: KeyCode,
+    pub brake: KeyCode,
+    pub steer_left: KeyCode,
+    pub steer_right: KeyCode,
+    pub handbrake: KeyCode,
+    pub reset: KeyCode,
+    pub shift_up: KeyCode,
+    pub shift_down: KeyCode,
+    pub toggle_automatic: KeyCode,
+}
+impl Default for KeyboardBindings {
+    fn default() -> Self {
+        Self {
+            throttle: KeyCode::W,
+            brake: KeyCode::S,
+            steer_left: KeyCode::A,
+            steer_right: KeyCode::D,
+            handbrake: KeyCode::LShift,
+            reset: KeyCode::Space,
+            shift_up: KeyCode::E,
+            shift_down: KeyCode::Q,
+            toggle_automatic: KeyCode::T,
+        }
+    }
+}
+impl KeyboardBindings {
+    pub fn key_mut(&mut self, action: InputAction) -> &mut KeyCode {
+        match action {
+            InputAction::Throttle => &mut self.throttle,
+            InputAction::Brake => &mut self.brake,
+            InputAction::SteerLeft => &mut self.steer_left,
+            InputAction::SteerRight => &mut self.steer_right,
+            InputAction::Handbrake => &mut self.handbrake,
+            InputAction::Reset => &mut self.reset,
+            InputAction::ShiftUp => &mut self.shift_up,
+            InputAction::ShiftDown => &mut self.shift_down,
+            InputAction::ToggleAutomatic => &mut self.toggle_automatic,
+        }
+    }
+}
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GamepadBindings {
+    /// Read as an analog value, so triggers give partial throttle.
+    pub throttle: GamepadButtonType,
+    pub brake: GamepadButtonType,
+    pub steer: GamepadAxisType,
+    pub handbrake: GamepadButtonType,
+    pub reset: GamepadButtonType,
+    pub shift_up: GamepadButtonType,
+    pub shift_down: GamepadButtonType,
+    pub toggle_automatic: GamepadButtonType,
+}
+impl Default for GamepadBindings {
+    fn default() -> Self {
+        Self {
+            throttle: GamepadButtonType::RightTrigger2,
+            brake: GamepadButtonType::LeftTrigger2,
+            steer: GamepadAxisType::LeftStickX,
+            handbrake: GamepadButtonType::South,
+            reset: GamepadButtonType::North,
+            shift_up: GamepadButtonType::RightTrigger,
+            shift_down: GamepadButtonType::LeftTrigger,
+            toggle_automatic: GamepadButtonType::Select,
+        }
+    }
+}
+impl GamepadBindings {
+    /// Steering is bound to a stick axis, so it has no button to rebind.
+    pub fn button_mut(&mut self, action: InputAction) -> Option<&mut GamepadButtonType> {
+        match action {
+            InputAction::Throttle => Some(&mut self.throttle),
+            InputAction::Brake => Some(&mut self.brake),
+            InputAction::SteerLeft | InputAction::SteerRight => None,
+            InputAction::Handbrake => Some(&mut self.handbrake),
+            InputAction::Reset => Some(&mut self.reset),
+            InputAction::ShiftUp => Some(&mut self.shift_up),
+            InputAction::ShiftDown => Some(&mut self.shift_down),
+            InputAction::ToggleAutomatic => Some(&mut self.toggle_automatic),
+        }
+    }
+}
+/// Keyboard and gamepad bindings, loaded from a `.bindings.ron` file. The resource of the
+/// same type holds the bindings in use, which can also be changed at runtime.
+#[derive(Resource, Clone, Debug, Default, Serialize, Deserialize, TypeUuid)]
+#[uuid = "0c2f5a51-7d3e-4b8a-9e61-4f3d2b7c8a19"]
+pub struct InputBindings {
+    #[serde(default)]
+    pub keyboard: KeyboardBindings,
+    #[serde(default)]
+    pub gamepad: GamepadBindings,
+}
+impl InputBindings {
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), RonFileError> {
+        ron_file::save(self, path)
+    }
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, RonFileError> {
+        ron_file::load(path)
+    }
+}
+#[derive(Default)]
+pub struct InputBindingsLoader;
+impl AssetLoader for InputBindingsLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let bindings: InputBindings = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(bindings));
+            Ok(())
+        })
+    }
+    fn extensions(&self) -> &[&str] {
+        &["bindings.ron"]
+    }
+}
+#[derive(Resource)]
+pub struct InputBindingsHandle(pub Handle<InputBindings>);
+/// File the rebound controls are written to, and read from instead of the bundled
+/// bindings when it exists.
+#[derive(Resource)]
+pub struct InputBindingsFile(pub PathBuf);
+impl Default for InputBindingsFile {
+    fn default() -> Self {
+        Self(PathBuf::from(BINDINGS_PATH))
+    }
+}
+/// Action waiting for a key or button while the controls are being rebound.
+#[derive(Resource, Default)]
+pub struct Rebinding {
+    pub action: Option<InputAction>,
+}
+/// Uses the controls the player rebound last time, or the bundled bindings if there are none.
+pub fn load_input_bindings(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    file: Res<InputBindingsFile>,
+    mut bindings: ResMut<InputBindings>,
+) {
+    if file.0.exists() {
+        match InputBindings::load(&file.0) {
+            Ok(saved) => {
+                *bindings = saved;
+                return;
+            }
+            Err(err) => error!("Could not load the input bindings from {}: {err}", file.0.display()),
+        }
+    }
+    commands.insert_resource(InputBindingsHandle(asset_server.load("input.bindings.ron")));
+}
+/// Replaces the bindings in use whenever the bindings file is (re)loaded.
+pub fn apply_input_bindings(
+    mut events: EventReader<AssetEvent<InputBindings>>,
+    assets: Res<Assets<InputBindings>>,
+    handle: Option<Res<InputBindingsHandle>>,
+    mut bindings: ResMut<InputBindings>,
+) {
+    let Some(handle) = handle else {
+        return;
+    };
+    for event in events.iter() {
+        if let AssetEvent::Created { handle: changed } | AssetEvent::Modified { handle: changed } = event {
+            if *changed == handle.0 {
+                if let Some(loaded) = assets.get(changed) {
+                    *bindings = loaded.clone();
+                }
+            }
+        }
+    }
+}
+/// F1 walks through every action asking for a new key or gamepad button, Escape stops.
+/// The bindings are saved to `InputBindingsFile` once rebinding stops.
+pub fn rebind_controls(
+    keys: Res<Input<KeyCode>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    file: Res<InputBindingsFile>,
+    mut bindings: ResMut<InputBindings>,
+    mut rebinding: ResMut<Rebinding>,
+) {
+    let Some(action) = rebinding.action else {
+        if keys.just_pressed(KeyCode::F1) {
+            rebinding.action = Some(InputAction::ALL[0]);
+            info!("Rebinding controls, press a key or button for {:?} (Escape to stop)", InputAction::ALL[0]);
+        }
+        return;
+    };
+    if keys.just_pressed(KeyCode::Escape) {
+        rebinding.action = None;
+        info!("Stopped rebinding controls");
+        save_input_bindings(&bindings, &file);
+        return;
+    }
+    let mut bound = false;
+    if let Some(&key) = keys.get_just_pressed().find(|&&key| key != KeyCode::F1) {
+        *bindings.keyboard.key_mut(action) = key;
+        bound = true;
+    }
+    if let Some(button) = gamepad_buttons.get_just_pressed().next() {
+        if let Some(binding) = bindings.gamepad.button_mut(action) {
+            *binding = button.button_type;
+            bound = true;
+        }
+    }
+    if !bound {
+        return;
+    }
+    let next = InputAction::ALL
+        .iter()
+        .position(|&other| other == action)
+        .and_then(|index| InputAction::ALL.get(index + 1))
+        .copied();
+    rebinding.action = next;
+    match next {
+        Some(next) => info!("Press a key or button for {next:?}"),
+        None => {
+            info!("Finished rebinding controls");
+            save_input_bindings(&bindings, &file);
+        }
+    }
+}
+fn save_input_bindings(bindings: &InputBindings, file: &InputBindingsFile) {
+    if let Err(err) = bindings.save(&file.0) {
+        error!("Could not save the input bindings to {}: {err}", file.0.display());
+    }
+}
+/// Releases the pedals and steering before the devices add their input for this frame.
+pub fn clear_vehicle_input(mut inputs: Query<&mut VehicleInput, With<HumanDriver>>) {
+    for mut input in inputs.iter_mut() {
+        input.throttle = 0.;
+        input.brake = 0.;
+        input.steer = 0.;
+        input.handbrake = false;
+    }
+}
+pub fn keyboard_input(
+    keys: Res<Input<KeyCode>>,
+    bindings: Res<InputBindings>,
+    rebinding: Res<Rebinding>,
+    mut inputs: Query<&mut VehicleInput, With<HumanDriver>>,
+) {
+    if rebinding.action.is_some() {
+        return;
+    }
+    let keyboard = &bindings.keyboard;
+    for mut input in inputs.iter_mut() {
+        if keys.pressed(keyboard.throttle) {
+            input.throttle = 1.;
+        }
+        if keys.pressed(keyboard.brake) {
+            input.brake = 1.;
+        }
+        if keys.pressed(keyboard.steer_left) {
+            input.steer -= 1.;
+        }
+        if keys.pressed(keyboard.steer_right) {
+            input.steer += 1.;
+        }
+        input.steer = input.steer.clamp(-1., 1.);
+        input.handbrake |= keys.pressed(keyboard.handbrake);
+        input.reset |= keys.just_pressed(keyboard.reset);
+        input.shift_up |= keys.just_pressed(keyboard.shift_up);
+        input.shift_down |= keys.just_pressed(keyboard.shift_down);
+        input.toggle_automatic |= keys.just_pressed(keyboard.toggle_automatic);
+    }
+}
+/// Takes gamepads that were unplugged back from their drivers, and gives every connected
+/// gamepad nobody drives with to a driver without one.
+pub fn assign_gamepads(gamepads: Res<Gamepads>, mut drivers: Query<&mut HumanDriver>) {
+    for mut driver in drivers.iter_mut() {
+        if driver.gamepad.is_some_and(|gamepad| !gamepads.contains(gamepad)) {
+            driver.gamepad = None;
+        }
+    }
+    let taken: Vec<Gamepad> = drivers.iter().filter_map(|driver| driver.gamepad).collect();
+    let mut free = gamepads.iter().filter(|gamepad| !taken.contains(gamepad));
+    for mut driver in drivers.iter_mut().filter(|driver| driver.gamepad.is_none()) {
+        let Some(gamepad) = free.next() else {
+            break;
+        };
+        driver.gamepad = Some(gamepad);
+    }
+}
+/// Adds the gamepad of each driver to its car's input.
+pub fn gamepad_input(
+    buttons: Res<Input<GamepadButton>>,
+    button_axes: Res<Axis<GamepadButton>>,
+    axes: Res<Axis<GamepadAxis>>,
+    bindings: Res<InputBindings>,
+    rebinding: Res<Rebinding>,
+    mut inputs: Query<(&mut VehicleInput, &HumanDriver)>,
+) {
+    if rebinding.action.is_some() {
+        return;
+    }
+    let pad = &bindings.gamepad;
+    for (mut input, driver) in inputs.iter_mut() {
+        let Some(gamepad) = driver.gamepad else {
+            continue;
+        };
+        let button = |button_type| GamepadButton::new(gamepad, button_type);
+        let throttle = button_axes.get(button(pad.throttle)).unwrap_or(0.);
+        let brake = button_axes.get(button(pad.brake)).unwrap_or(0.);
+        let steer = axes.get(GamepadAxis::new(gamepad, pad.steer)).unwrap_or(0.);
+        input.throttle = input.throttle.max(throttle);
+        input.brake = input.brake.max(brake);
+        input.steer = (input.steer + steer).clamp(-1., 1.);
+        input.handbrake |= buttons.pressed(button(pad.handbrake));
+        input.reset |= buttons.just_pressed(button(pad.reset));
+        input.shift_up |= buttons.just_pressed(button(pad.shift_up));
+        input.shift_down |= buttons.just_pressed(button(pad.shift_down));
+        input.toggle_automatic |= buttons.just_pressed(button(pad.toggle_automatic));
+    }
+}
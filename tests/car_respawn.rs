@@ -0,0 +1,93 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use car_game::{
+    car_respawn::{CarRespawn, RespawnSettings},
+    car_spawn::CarSpec,
+    car_suspension::CarPhysics,
+    checkpoints::CheckpointSpec,
+    headless::{headless_app, spawn_test_car, spawn_test_surface, step_frames},
+    track::{ActiveTrack, TrackManifest},
+    vehicle_input::VehicleInput,
+};
+
+fn transform(app: &App, car: Entity) -> Transform {
+    *app.world.get::<Transform>(car).unwrap()
+}
+fn input(app: &mut App, car: Entity) -> Mut<'_, VehicleInput> {
+    app.world.get_mut::<VehicleInput>(car).unwrap()
+}
+
+#[test]
+fn reset_puts_the_car_back_at_the_last_checkpoint() {
+    let mut app = headless_app();
+    spawn_test_surface(&mut app, 500.);
+    let layout = TrackManifest {
+        name: "Straight".to_string(),
+        checkpoints: [-5., -25., -200.]
+            .into_iter()
+            .map(|z| CheckpointSpec {
+                translation: Vec3::new(0., 1., z),
+                rotation: Quat::IDENTITY,
+                half_extents: Vec3::new(10., 3., 0.5),
+            })
+            .collect(),
+        circuit: false,
+        laps: 1,
+        ..default()
+    };
+    let handle = app.world.resource_mut::<Assets<TrackManifest>>().add(layout);
+    app.insert_resource(ActiveTrack(handle));
+    let car = spawn_test_car(&mut app, &CarSpec::default(), Transform::from_xyz(0., 1., 0.));
+    step_frames(&mut app, 120);
+    while transform(&app, car).translation.z > -50. {
+        input(&mut app, car).throttle = 1.;
+        step_frames(&mut app, 1);
+    }
+    input(&mut app, car).reset = true;
+    step_frames(&mut app, 1);
+    let reset = transform(&app, car);
+    assert!((reset.translation.z + 25.).abs() < 2., "reset to {}", reset.translation);
+    assert!(reset.forward().dot(Vec3::NEG_Z) > 0.99);
+    assert!(app.world.get::<Velocity>(car).unwrap().linvel.length() < 0.5);
+    step_frames(&mut app, 60);
+    assert!(transform(&app, car).up().y > 0.99);
+}
+#[test]
+fn car_on_its_roof_is_turned_back_over() {
+    let mut app = headless_app();
+    spawn_test_surface(&mut app, 500.);
+    let upside_down = Transform::from_xyz(0., 1., 0.).with_rotation(Quat::from_rotation_z(std::f32::consts::PI));
+    let car = spawn_test_car(&mut app, &CarSpec::default(), upside_down);
+    step_frames(&mut app, 60);
+    assert!(transform(&app, car).up().y < 0.);
+    let settings = *app.world.resource::<RespawnSettings>();
+    step_frames(&mut app, (settings.upside_down_time * 60.) as usize + 60);
+    assert!(transform(&app, car).up().y > 0.99);
+}
+#[test]
+fn car_falling_off_the_world_is_put_back() {
+    let mut app = headless_app();
+    app.insert_resource(RespawnSettings {
+        kill_height: -5.,
+        ..default()
+    });
+    let car = spawn_test_car(&mut app, &CarSpec::default(), Transform::from_xyz(0., 1., 0.));
+    for _ in 0..180 {
+        step_frames(&mut app, 1);
+        assert!(transform(&app, car).translation.y > -6.);
+    }
+}
+#[test]
+fn respawned_car_does_not_keep_the_ground_it_left() {
+    let mut app = headless_app();
+    spawn_test_surface(&mut app, 500.);
+    let car = spawn_test_car(&mut app, &CarSpec::default(), Transform::from_xyz(0., 1., 0.));
+    step_frames(&mut app, 120);
+    assert!(app.world.get::<CarPhysics>(car).unwrap().wheel_infos.iter().all(|wheel| wheel.hit));
+    app.world.get_mut::<CarRespawn>(car).unwrap().checkpoint = Some(Transform::from_xyz(0., 20., 0.));
+    input(&mut app, car).reset = true;
+    step_frames(&mut app, 1);
+    // the wheels are cast from up in the air, so nothing pushes the car on the first step
+    assert!(app.world.get::<CarPhysics>(car).unwrap().wheel_infos.iter().all(|wheel| !wheel.hit));
+    assert!(app.world.get::<Velocity>(car).unwrap().linvel.y <= 0.);
+}
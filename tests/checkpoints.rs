@@ -0,0 +1,173 @@
+use bevy::{ecs::event::Events, prelude::*};
+use bevy_rapier3d::prelude::*;
+use car_game::{
+    car_spawn::CarSpec,
+    checkpoints::{CheckpointPassed, CheckpointSpec, LapCompleted, RaceFinished, RaceProgress, WrongWay},
+    headless::{headless_app, spawn_test_car, spawn_test_surface, step_frames},
+    timer_text::Completion,
+    track::{ActiveTrack, TrackManifest},
+    vehicle_input::{HumanDriver, InputBindings, VehicleInput},
+};
+
+/// Gates across the straight the car drives down, 20 units apart.
+fn straight_layout(circuit: bool) -> TrackManifest {
+    TrackManifest {
+        name: "Straight".to_string(),
+        checkpoints: [-5., -25., -45.]
+            .into_iter()
+            .map(|z| CheckpointSpec {
+                translation: Vec3::new(0., 1., z),
+                rotation: Quat::IDENTITY,
+                half_extents: Vec3::new(10., 3., 0.5),
+            })
+            .collect(),
+        circuit,
+        laps: 1,
+        ..default()
+    }
+}
+/// Settled car at the origin in front of the checkpoints of `layout`.
+fn race(layout: TrackManifest) -> (App, Entity) {
+    let mut app = headless_app();
+    spawn_test_surface(&mut app, 500.);
+    let handle = app.world.resource_mut::<Assets<TrackManifest>>().add(layout);
+    app.insert_resource(ActiveTrack(handle));
+    let car = spawn_test_car(&mut app, &CarSpec::default(), Transform::from_xyz(0., 1., 0.));
+    step_frames(&mut app, 120);
+    (app, car)
+}
+/// Race events, gathered every frame since they only live for two updates.
+#[derive(Default)]
+struct Log {
+    passed: Vec<usize>,
+    laps: Vec<u32>,
+    finished: usize,
+    wrong_way: Vec<bool>,
+}
+impl Log {
+    fn collect(&mut self, app: &mut App) {
+        self.passed.extend(drain::<CheckpointPassed>(app).into_iter().map(|event| event.checkpoint));
+        self.laps.extend(drain::<LapCompleted>(app).into_iter().map(|event| event.lap));
+        self.finished += drain::<RaceFinished>(app).len();
+        self.wrong_way.extend(drain::<WrongWay>(app).into_iter().map(|event| event.wrong_way));
+    }
+}
+/// Drives with the given pedals until the car's `z` satisfies `until`.
+fn drive(app: &mut App, car: Entity, log: &mut Log, throttle: f32, brake: f32, until: impl Fn(f32) -> bool) {
+    for _ in 0..600 {
+        let mut input = app.world.get_mut::<VehicleInput>(car).unwrap();
+        input.throttle = throttle;
+        input.brake = brake;
+        step_frames(app, 1);
+        log.collect(app);
+        if until(app.world.get::<Transform>(car).unwrap().translation.z) {
+            return;
+        }
+    }
+    panic!("car never got there, stopped at {:?}", app.world.get::<Transform>(car).unwrap().translation);
+}
+fn progress(app: &App, car: Entity) -> RaceProgress {
+    app.world.get::<RaceProgress>(car).unwrap().clone()
+}
+fn drain<E: Send + Sync + 'static>(app: &mut App) -> Vec<E> {
+    app.world.resource_mut::<Events<E>>().drain().collect()
+}
+
+#[test]
+fn finishes_after_passing_every_checkpoint_in_order() {
+    let (mut app, car) = race(straight_layout(false));
+    let mut log = Log::default();
+    drive(&mut app, car, &mut log, 1., 0., |z| z < -50.);
+    assert_eq!(log.passed, [0, 1, 2]);
+    assert_eq!(log.laps, [1]);
+    assert_eq!(log.finished, 1);
+    assert!(progress(&app, car).finished);
+}
+#[test]
+fn sprint_needs_a_finish_after_its_start_line() {
+    let mut layout = straight_layout(false);
+    layout.checkpoints.truncate(1);
+    // crossing the only gate starts the race, and nothing would ever end it
+    assert!(layout.validate().is_err());
+    assert!(TrackManifest { circuit: true, ..layout.clone() }.validate().is_ok());
+    assert!(TrackManifest { checkpoints: Vec::new(), ..layout.clone() }.validate().is_ok());
+    let finish = straight_layout(false).checkpoints.pop();
+    let layout = TrackManifest { finish, ..layout };
+    assert!(layout.validate().is_ok());
+    let (mut app, car) = race(layout);
+    let mut log = Log::default();
+    drive(&mut app, car, &mut log, 1., 0., |z| z < -50.);
+    assert_eq!(log.passed, [0, 1]);
+    assert_eq!(log.finished, 1);
+}
+#[test]
+fn skipping_a_checkpoint_does_not_finish() {
+    let mut layout = straight_layout(false);
+    // moved off to the side of the road
+    layout.checkpoints[1].translation.x = 50.;
+    let (mut app, car) = race(layout);
+    let mut log = Log::default();
+    drive(&mut app, car, &mut log, 1., 0., |z| z < -50.);
+    assert_eq!(log.passed, [0]);
+    assert_eq!(log.finished, 0);
+    let progress = progress(&app, car);
+    assert!(!progress.finished);
+    assert_eq!(progress.next_checkpoint, 1);
+}
+#[test]
+fn reversing_through_a_passed_checkpoint_is_the_wrong_way() {
+    let mut layout = straight_layout(true);
+    // far enough for the car to stop before it
+    layout.checkpoints[2].translation.z = -100.;
+    let (mut app, car) = race(layout);
+    let mut log = Log::default();
+    drive(&mut app, car, &mut log, 1., 0., |z| z < -32.);
+    drive(&mut app, car, &mut log, 0., 1., |z| z > -20.);
+    assert_eq!(log.passed, [0, 1]);
+    assert_eq!(log.wrong_way, [true]);
+    assert!(progress(&app, car).wrong_way);
+    assert_eq!(progress(&app, car).next_checkpoint, 2);
+}
+#[test]
+fn turning_around_between_checkpoints_is_the_wrong_way() {
+    let (mut app, car) = race(straight_layout(true));
+    let mut log = Log::default();
+    drive(&mut app, car, &mut log, 1., 0., |z| z < -8.);
+    assert_eq!(log.passed, [0]);
+    // turned around short of the next gate, without going back through the start line
+    let turn_around = |app: &mut App, rotation: Quat| {
+        let mut transform = app.world.get_mut::<Transform>(car).unwrap();
+        transform.rotation = rotation;
+        *app.world.get_mut::<Velocity>(car).unwrap() = Velocity::zero();
+    };
+    turn_around(&mut app, Quat::from_rotation_y(std::f32::consts::PI));
+    drive(&mut app, car, &mut log, 1., 0., |z| z > -6.);
+    assert_eq!(log.passed, [0]);
+    assert_eq!(log.wrong_way, [true]);
+    assert!(progress(&app, car).wrong_way);
+    turn_around(&mut app, Quat::IDENTITY);
+    drive(&mut app, car, &mut log, 1., 0., |z| z < -20.);
+    assert_eq!(log.wrong_way, [true, false]);
+    assert!(!progress(&app, car).wrong_way);
+    assert_eq!(progress(&app, car).next_checkpoint, 1);
+}
+#[test]
+fn the_players_splits_and_lap_are_timed() {
+    let (mut app, car) = race(straight_layout(false));
+    app.world.entity_mut(car).insert(HumanDriver::default());
+    // the player's input comes from the keyboard
+    let throttle = app.world.resource::<InputBindings>().keyboard.throttle;
+    app.world.resource_mut::<Input<KeyCode>>().press(throttle);
+    let mut log = Log::default();
+    drive(&mut app, car, &mut log, 0., 0., |z| z < -50.);
+    let completion = app.world.resource::<Completion>();
+    assert!(completion.finished);
+    // the start line is not a split, so the lap has one for each of the other checkpoints
+    let best = completion.best_lap.as_ref().unwrap();
+    assert_eq!(best.splits.len(), 2);
+    assert!(best.splits[0] > 0. && best.splits[0] < best.splits[1]);
+    assert_eq!(best.time, best.splits[1]);
+    assert_eq!(completion.laps, [best.time]);
+    assert_eq!(completion.elapsed, best.time);
+    assert!(completion.delta.is_none());
+}
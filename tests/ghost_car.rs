@@ -0,0 +1,164 @@
+use bevy::prelude::*;
+use car_game::{
+    car_spawn::CarSpec,
+    ghost_car::{
+        move_ghost_car, record_ghost, spawn_ghost_car, BestGhost, GhostCar, GhostFolder, GhostRecording, GhostRun, GhostSample,
+    },
+    headless::{headless_app, spawn_test_car, spawn_test_surface, step_frames},
+    timer_text::Completion,
+    vehicle_input::HumanDriver,
+    VehicleSet,
+};
+
+/// Run along the z axis, turning a quarter around the y axis by the third sample.
+fn straight_run() -> GhostRun {
+    GhostRun {
+        sample_interval: 0.5,
+        samples: [(0., 0.), (-2., 0.25), (-6., 0.5)]
+            .into_iter()
+            .map(|(z, turn)| GhostSample {
+                translation: Vec3::new(0., 0., z),
+                rotation: Quat::from_rotation_y(std::f32::consts::PI * turn),
+            })
+            .collect(),
+        time: 1.,
+        ..default()
+    }
+}
+fn ghosts(app: &mut App) -> Vec<String> {
+    let mut query = app.world.query::<&GhostCar>();
+    query.iter(&app.world).map(|ghost| ghost.body_model.clone()).collect()
+}
+/// Headless app recording the player's car on `track` against a best run of `best_time`,
+/// until the player finishes after `frames` steps. Runs are saved to the temp folder.
+fn record_against(track: &str, best_time: f32, frames: usize) -> App {
+    let mut app = headless_app();
+    app.init_resource::<BestGhost>()
+        .init_resource::<GhostRecording>()
+        .insert_resource(GhostFolder(std::env::temp_dir().join(format!("car_game_ghosts_{}", std::process::id()))))
+        .add_system(record_ghost.after(VehicleSet::Controls).in_schedule(CoreSchedule::FixedUpdate));
+    spawn_test_surface(&mut app, 500.);
+    let spec = CarSpec {
+        body_model: "truck.glb#Scene0".to_string(),
+        ..default()
+    };
+    let car = spawn_test_car(&mut app, &spec, Transform::from_xyz(0., 1., 0.));
+    app.world.entity_mut(car).insert(HumanDriver::default());
+    app.insert_resource(GhostRecording {
+        track: track.to_string(),
+        ..default()
+    });
+    app.insert_resource(BestGhost(Some(GhostRun {
+        time: best_time,
+        ..straight_run()
+    })));
+    app.world.resource_mut::<Completion>().started = true;
+    step_frames(&mut app, frames);
+    app.world.resource_mut::<Completion>().finished = true;
+    step_frames(&mut app, 1);
+    app
+}
+
+#[test]
+fn samples_are_blended_in_between() {
+    let run = straight_run();
+    let halfway = run.sample(0.25).unwrap();
+    assert!(halfway.translation.distance(Vec3::new(0., 0., -1.)) < 1e-5);
+    let angle = halfway.rotation.angle_between(Quat::from_rotation_y(std::f32::consts::PI / 8.));
+    assert!(angle < 1e-3, "off by {angle}");
+    let later = run.sample(0.75).unwrap();
+    assert!(later.translation.distance(Vec3::new(0., 0., -4.)) < 1e-5);
+}
+#[test]
+fn last_sample_is_held_after_the_run() {
+    let run = straight_run();
+    for time in [1., 1.2, 60.] {
+        let end = run.sample(time).unwrap();
+        assert!(end.translation.distance(Vec3::new(0., 0., -6.)) < 1e-5, "at {time}");
+    }
+}
+#[test]
+fn time_before_the_start_is_the_first_sample() {
+    let start = straight_run().sample(-3.).unwrap();
+    assert_eq!(start.translation, Vec3::ZERO);
+    assert!(start.rotation.angle_between(Quat::IDENTITY) < 1e-5);
+}
+#[test]
+fn empty_run_has_no_samples() {
+    assert!(GhostRun::default().sample(0.).is_none());
+    assert!(GhostRun::default().sample(2.).is_none());
+}
+#[test]
+fn run_survives_a_save_and_load() {
+    let path = std::env::temp_dir().join(format!("car_game_{}.ghost.ron", std::process::id()));
+    let run = GhostRun {
+        body_model: "truck.glb#Scene0".to_string(),
+        ..straight_run()
+    };
+    run.save(&path).unwrap();
+    let loaded = GhostRun::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(loaded.time, run.time);
+    assert_eq!(loaded.sample_interval, run.sample_interval);
+    assert_eq!(loaded.body_model, run.body_model);
+    for (loaded, sample) in loaded.samples.iter().zip(run.samples.iter()) {
+        assert_eq!(loaded.translation, sample.translation);
+        assert_eq!(loaded.rotation, sample.rotation);
+    }
+}
+#[test]
+fn slower_run_does_not_replace_the_best() {
+    let track = format!("Ghost slower {}", std::process::id());
+    let app = record_against(&track, 0.2, 60);
+    assert!(app.world.resource::<GhostRecording>().saved);
+    assert_eq!(app.world.resource::<BestGhost>().0.as_ref().unwrap().time, 0.2);
+    assert!(!app.world.resource::<GhostFolder>().ghost_path(&track).exists());
+}
+#[test]
+fn faster_run_replaces_the_best_and_is_saved() {
+    let track = format!("Ghost faster {}", std::process::id());
+    let app = record_against(&track, 100., 60);
+    let best = app.world.resource::<BestGhost>().0.clone().unwrap();
+    assert!((best.time - 1.).abs() < 0.05, "best run of {}", best.time);
+    assert_eq!(best.samples.len(), 10);
+    assert_eq!(best.body_model, "truck.glb#Scene0");
+    let path = app.world.resource::<GhostFolder>().ghost_path(&track);
+    assert!(path.starts_with(std::env::temp_dir()));
+    let saved = GhostRun::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(saved.time, best.time);
+}
+#[test]
+fn ghost_looks_like_the_car_that_drove_the_run() {
+    let mut app = headless_app();
+    app.init_resource::<BestGhost>().add_system(spawn_ghost_car);
+    let run = |body_model: &str| GhostRun {
+        body_model: body_model.to_string(),
+        ..default()
+    };
+    app.insert_resource(BestGhost(Some(run("truck.glb#Scene0"))));
+    step_frames(&mut app, 2);
+    assert_eq!(ghosts(&mut app), ["truck.glb#Scene0"]);
+    app.insert_resource(BestGhost(Some(run("van.glb#Scene0"))));
+    step_frames(&mut app, 2);
+    assert_eq!(ghosts(&mut app), ["van.glb#Scene0"]);
+}
+#[test]
+fn ghost_keeps_to_the_race_clock_whatever_the_frame_rate() {
+    let mut app = headless_app();
+    app.insert_resource(BestGhost(Some(straight_run()))).add_system(move_ghost_car);
+    let ghost = app
+        .world
+        .spawn((TransformBundle::default(), VisibilityBundle::default(), GhostCar::default()))
+        .id();
+    let mut completion = app.world.resource_mut::<Completion>();
+    completion.started = true;
+    completion.finished = true;
+    completion.elapsed = 0.75;
+    // frames come and go while the clock stands still, and so does the ghost
+    for _ in 0..5 {
+        step_frames(&mut app, 1);
+        let transform = app.world.get::<Transform>(ghost).unwrap();
+        assert!(transform.translation.distance(Vec3::new(0., 0., -4.)) < 1e-5);
+    }
+}
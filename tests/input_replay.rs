@@ -0,0 +1,55 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use car_game::{
+    car_spawn::CarSpec,
+    headless::{headless_app, run_commands, spawn_test_surface, step_frames},
+    input_replay::{start_recording, start_replay, InputRecorder, InputRecording, InputReplay, SimulationSeed},
+    vehicle_input::VehicleInput,
+};
+
+const FRAMES: usize = 300;
+
+/// Accelerates, turns left, brakes and pulls the handbrake.
+fn scripted_input(frame: usize) -> VehicleInput {
+    VehicleInput {
+        throttle: if frame < 150 { 1. } else { 0. },
+        brake: if (200..240).contains(&frame) { 1. } else { 0. },
+        steer: if (60..180).contains(&frame) { -0.6 } else { 0. },
+        handbrake: (240..260).contains(&frame),
+        ..default()
+    }
+}
+
+#[test]
+fn replay_reproduces_the_recorded_run() {
+    let mut app = headless_app();
+    spawn_test_surface(&mut app, 500.);
+    let start = Transform::from_xyz(3., 1., -2.).with_rotation(Quat::from_rotation_y(0.4));
+    let car = run_commands(&mut app, |commands, asset_server| {
+        start_recording(commands, asset_server, &CarSpec::default(), start, SimulationSeed(7))
+    });
+    for frame in 0..FRAMES {
+        *app.world.get_mut::<VehicleInput>(car).unwrap() = scripted_input(frame);
+        step_frames(&mut app, 1);
+    }
+    let recorded_end = *app.world.get::<Transform>(car).unwrap();
+    let recorded_velocity = *app.world.get::<Velocity>(car).unwrap();
+    let recording = app.world.get::<InputRecorder>(car).unwrap().0.clone();
+    assert_eq!(recording.frames.len(), FRAMES);
+
+    let path = std::env::temp_dir().join(format!("car_game_replay_{}.ron", std::process::id()));
+    recording.save(&path).unwrap();
+    let loaded = InputRecording::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(loaded.frames, recording.frames);
+
+    let mut app = headless_app();
+    spawn_test_surface(&mut app, 500.);
+    let car = run_commands(&mut app, |commands, asset_server| start_replay(commands, asset_server, loaded));
+    step_frames(&mut app, FRAMES);
+    assert!(app.world.get::<InputReplay>(car).unwrap().finished());
+    assert_eq!(app.world.resource::<SimulationSeed>().0, 7);
+    assert_eq!(*app.world.get::<Transform>(car).unwrap(), recorded_end);
+    assert_eq!(*app.world.get::<Velocity>(car).unwrap(), recorded_velocity);
+    assert!(recorded_end.translation.distance(start.translation) > 10.);
+}
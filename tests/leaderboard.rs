@@ -0,0 +1,102 @@
+use std::path::PathBuf;
+
+use bevy::prelude::*;
+use car_game::{
+    car_spawn::CarSpec,
+    checkpoints::CheckpointSpec,
+    headless::{headless_app, spawn_test_car, spawn_test_surface, step_frames},
+    leaderboard::{format_date, Leaderboard, LeaderboardEntry, LeaderboardPlugin, RaceResult},
+    ron_file::RonFileError,
+    track::{ActiveTrack, TrackManifest},
+    vehicle_input::{HumanDriver, InputBindings, VehicleInput},
+};
+
+fn entry(track: &str, car: &str, time: f32) -> LeaderboardEntry {
+    LeaderboardEntry {
+        track: track.to_string(),
+        car: car.to_string(),
+        player: "Tester".to_string(),
+        time,
+        date: 0,
+    }
+}
+/// Headless app saving its leaderboard to a fresh file named after the test, with the
+/// player's car on a short straight and the throttle held down.
+fn race(name: &str) -> (App, Entity, PathBuf) {
+    let path = std::env::temp_dir().join(format!("car_game_{name}.leaderboard.ron"));
+    let _ = std::fs::remove_file(&path);
+    let mut app = headless_app();
+    app.add_plugin(LeaderboardPlugin { path: path.clone() });
+    spawn_test_surface(&mut app, 500.);
+    let layout = TrackManifest {
+        name: "Straight".to_string(),
+        checkpoints: [-5., -40.]
+            .into_iter()
+            .map(|z| CheckpointSpec {
+                translation: Vec3::new(0., 1., z),
+                rotation: Quat::IDENTITY,
+                half_extents: Vec3::new(10., 3., 0.5),
+            })
+            .collect(),
+        circuit: false,
+        laps: 1,
+        ..default()
+    };
+    let handle = app.world.resource_mut::<Assets<TrackManifest>>().add(layout);
+    app.insert_resource(ActiveTrack(handle));
+    let car = spawn_test_car(&mut app, &CarSpec::default(), Transform::from_xyz(0., 1., 0.));
+    app.world.entity_mut(car).insert(HumanDriver::default());
+    step_frames(&mut app, 120);
+    let throttle = app.world.resource::<InputBindings>().keyboard.throttle;
+    app.world.resource_mut::<Input<KeyCode>>().press(throttle);
+    (app, car, path)
+}
+
+#[test]
+fn places_are_counted_per_track_and_car() {
+    let mut leaderboard = Leaderboard::default();
+    assert_eq!(leaderboard.insert(entry("Racetrack", "Car", 60.)), 1);
+    assert_eq!(leaderboard.insert(entry("Racetrack", "Car", 50.)), 1);
+    assert_eq!(leaderboard.insert(entry("Racetrack", "Car", 55.)), 2);
+    assert_eq!(leaderboard.insert(entry("Racetrack", "Truck", 70.)), 1);
+    assert_eq!(leaderboard.insert(entry("Hills", "Car", 80.)), 1);
+    let times: Vec<f32> = leaderboard.top("Racetrack", "Car", 2).iter().map(|entry| entry.time).collect();
+    assert_eq!(times, [50., 55.]);
+}
+#[test]
+fn dates_are_formatted_in_utc() {
+    assert_eq!(format_date(0), "1970-01-01");
+    assert_eq!(format_date(951_825_600), "2000-02-29");
+    assert_eq!(format_date(1_700_000_000), "2023-11-14");
+}
+#[test]
+fn finish_time_is_saved_to_the_leaderboard() {
+    let (mut app, _, path) = race("saved");
+    step_frames(&mut app, 600);
+    let result = app.world.resource::<RaceResult>().0.clone().unwrap();
+    assert_eq!(result.place, Some(1));
+    assert_eq!(result.entry.track, "Straight");
+    assert_eq!(result.entry.car, CarSpec::default().name);
+    let saved = Leaderboard::load(&path).unwrap();
+    assert_eq!(saved.entries, [result.entry]);
+    std::fs::remove_file(path).unwrap();
+}
+#[test]
+fn interrupted_run_is_not_saved() {
+    let (mut app, car, path) = race("interrupted");
+    step_frames(&mut app, 30);
+    app.world.get_mut::<VehicleInput>(car).unwrap().reset = true;
+    step_frames(&mut app, 600);
+    let result = app.world.resource::<RaceResult>().0.clone().unwrap();
+    assert_eq!(result.place, None);
+    assert!(!path.exists());
+}
+#[test]
+fn unreadable_leaderboard_is_not_called_a_recording() {
+    let path = std::env::temp_dir().join(format!("car_game_broken_{}.leaderboard.ron", std::process::id()));
+    std::fs::write(&path, "(entries: [oops])").unwrap();
+    let err = Leaderboard::load(&path).unwrap_err();
+    std::fs::remove_file(&path).unwrap();
+    assert!(matches!(err, RonFileError::Deserialize(_)));
+    assert!(!err.to_string().contains("recording"), "{err}");
+}
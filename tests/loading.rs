@@ -0,0 +1,52 @@
+use bevy::prelude::*;
+use car_game::{
+    headless::{headless_app, step_frames},
+    loading::{AssetsLoading, LoadFailure, LoadingProgress},
+    race_state::RaceState,
+    track::TrackManifest,
+};
+
+/// Headless app back in the loading state, waiting for `paths`.
+fn loading(paths: &[&str]) -> App {
+    let mut app = headless_app();
+    let handles: Vec<HandleUntyped> = paths
+        .iter()
+        .map(|path| app.world.resource::<AssetServer>().load::<TrackManifest, _>(*path).clone_untyped())
+        .collect();
+    app.insert_resource(AssetsLoading(handles));
+    app.insert_resource(NextState(Some(RaceState::Loading)));
+    step_frames(&mut app, 1);
+    app
+}
+fn state(app: &App) -> RaceState {
+    app.world.resource::<State<RaceState>>().0
+}
+
+#[test]
+fn progress_counts_the_loaded_assets() {
+    let mut app = loading(&["tracks/racetrack.track.ron"]);
+    for _ in 0..100 {
+        if app.world.resource::<LoadingProgress>().done() {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        step_frames(&mut app, 1);
+    }
+    let progress = *app.world.resource::<LoadingProgress>();
+    assert_eq!((progress.loaded, progress.total), (1, 1));
+    // leaving the loading state is up to the app, once the map is built
+    assert_eq!(state(&app), RaceState::Loading);
+}
+#[test]
+fn failed_asset_is_named_on_the_error_screen() {
+    let mut app = loading(&["tracks/racetrack.track.ron", "missing.track.ron"]);
+    for _ in 0..100 {
+        if state(&app) == RaceState::Failed {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        step_frames(&mut app, 1);
+    }
+    assert_eq!(state(&app), RaceState::Failed);
+    assert_eq!(app.world.resource::<LoadFailure>().asset, "missing.track.ron");
+}
@@ -0,0 +1,113 @@
+use bevy::{
+    input::{keyboard::KeyboardInput, ButtonState},
+    prelude::*,
+};
+use car_game::{
+    car_spawn::CarSpec,
+    headless::{headless_app, spawn_test_car, spawn_test_surface, step_frames},
+    race_state::{RaceState, StartTransform, COUNTDOWN_SECONDS},
+    timer_text::Completion,
+    vehicle_input::{HumanDriver, InputBindings},
+    PHYSICS_TIMESTEP,
+};
+
+/// Player's car settled at the start.
+fn player_car() -> (App, Entity) {
+    let mut app = headless_app();
+    spawn_test_surface(&mut app, 500.);
+    let start = Transform::from_xyz(0., 1., 0.);
+    app.insert_resource(StartTransform(start));
+    let car = spawn_test_car(&mut app, &CarSpec::default(), start);
+    app.world.entity_mut(car).insert(HumanDriver::default());
+    step_frames(&mut app, 120);
+    (app, car)
+}
+fn hold_throttle(app: &mut App) {
+    let throttle = app.world.resource::<InputBindings>().keyboard.throttle;
+    app.world.resource_mut::<Input<KeyCode>>().press(throttle);
+}
+fn tap(app: &mut App, key_code: KeyCode) {
+    app.world.send_event(KeyboardInput {
+        scan_code: 0,
+        key_code: Some(key_code),
+        state: ButtonState::Pressed,
+    });
+    step_frames(app, 1);
+    app.world.send_event(KeyboardInput {
+        scan_code: 0,
+        key_code: Some(key_code),
+        state: ButtonState::Released,
+    });
+    step_frames(app, 1);
+}
+fn state(app: &App) -> RaceState {
+    app.world.resource::<State<RaceState>>().0
+}
+fn player(app: &mut App) -> (Entity, Transform) {
+    let mut query = app.world.query_filtered::<(Entity, &Transform), With<HumanDriver>>();
+    let (entity, transform) = query.single(&app.world);
+    (entity, *transform)
+}
+
+#[test]
+fn countdown_locks_the_controls_until_go() {
+    let (mut app, _) = player_car();
+    app.insert_resource(NextState(Some(RaceState::Countdown)));
+    step_frames(&mut app, 1);
+    hold_throttle(&mut app);
+    step_frames(&mut app, 60);
+    assert_eq!(state(&app), RaceState::Countdown);
+    assert!(player(&mut app).1.translation.z.abs() < 0.05);
+    assert!(!app.world.resource::<Completion>().started);
+    step_frames(&mut app, (COUNTDOWN_SECONDS / PHYSICS_TIMESTEP) as usize);
+    assert_eq!(state(&app), RaceState::Racing);
+    assert!(app.world.resource::<Completion>().started);
+    step_frames(&mut app, 60);
+    assert!(player(&mut app).1.translation.z < -1.);
+}
+#[test]
+fn pause_stops_the_car_and_the_timer() {
+    let (mut app, _) = player_car();
+    hold_throttle(&mut app);
+    step_frames(&mut app, 60);
+    tap(&mut app, KeyCode::P);
+    assert_eq!(state(&app), RaceState::Paused);
+    let (_, paused_at) = player(&mut app);
+    let elapsed = app.world.resource::<Completion>().elapsed;
+    step_frames(&mut app, 60);
+    assert_eq!(player(&mut app).1, paused_at);
+    assert_eq!(app.world.resource::<Completion>().elapsed, elapsed);
+    tap(&mut app, KeyCode::P);
+    assert_eq!(state(&app), RaceState::Racing);
+    step_frames(&mut app, 10);
+    assert!(player(&mut app).1.translation.z < paused_at.translation.z);
+}
+#[test]
+fn restart_puts_a_new_car_at_the_start() {
+    let (mut app, car) = player_car();
+    hold_throttle(&mut app);
+    step_frames(&mut app, 120);
+    tap(&mut app, KeyCode::R);
+    assert_eq!(state(&app), RaceState::Countdown);
+    let (new_car, transform) = player(&mut app);
+    assert_ne!(new_car, car);
+    assert!(app.world.get_entity(car).is_none());
+    assert!(transform.translation.distance(Vec3::new(0., 1., 0.)) < 0.1);
+    let completion = app.world.resource::<Completion>();
+    assert!(!completion.started);
+    assert_eq!(completion.elapsed, 0.);
+}
+#[test]
+fn escape_from_the_pause_leaves_for_the_track_selection() {
+    let (mut app, _) = player_car();
+    hold_throttle(&mut app);
+    step_frames(&mut app, 30);
+    tap(&mut app, KeyCode::P);
+    assert_eq!(state(&app), RaceState::Paused);
+    tap(&mut app, KeyCode::Escape);
+    assert_eq!(state(&app), RaceState::TrackSelect);
+    let mut cars = app.world.query_filtered::<Entity, With<HumanDriver>>();
+    assert_eq!(cars.iter(&app.world).count(), 0);
+    let completion = app.world.resource::<Completion>();
+    assert!(!completion.started && completion.elapsed == 0.);
+}
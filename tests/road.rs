@@ -0,0 +1,106 @@
+use bevy::{ecs::event::Events, math::Vec3Swizzles, prelude::*};
+use bevy_rapier3d::prelude::*;
+use car_game::{
+    car_spawn::CarSpec,
+    car_suspension::CarPhysics,
+    checkpoints::CheckpointPassed,
+    headless::{headless_app, spawn_test_car, step_frames},
+    road::{Road, RoadPoint, RoadSettings},
+    track::{ActiveTrack, TrackManifest},
+    vehicle_input::VehicleInput,
+};
+
+/// Loop through four points on a circle 200 units across, with the far side raised and banked.
+fn loop_settings() -> RoadSettings {
+    let corners = [(0., 0., 0.), (100., 0., -100.), (0., 10., -200.), (-100., 0., -100.)];
+    let points = corners
+        .into_iter()
+        .map(|(x, y, z)| RoadPoint {
+            translation: Vec3::new(x, y, z),
+            width: 12.,
+            banking: if y > 0. { 0.2 } else { 0. },
+        })
+        .collect();
+    RoadSettings {
+        checkpoints: 8,
+        ..RoadSettings::new(points)
+    }
+}
+fn cast_down(app: &App, point: Vec3) -> Option<f32> {
+    let rapier_context = app.world.resource::<RapierContext>();
+    let origin = point + Vec3::Y * 20.;
+    rapier_context
+        .cast_ray(origin, Vec3::NEG_Y, 40., true, QueryFilter::default())
+        .map(|(_, toi)| origin.y - toi)
+}
+
+#[test]
+fn roads_need_a_closed_loop_of_points() {
+    let mut settings = loop_settings();
+    settings.points.truncate(2);
+    assert!(Road::generate(&settings).is_err());
+    let mut settings = loop_settings();
+    settings.points[1].width = 0.;
+    assert!(Road::generate(&settings).is_err());
+}
+#[test]
+fn checkpoints_are_spread_along_the_road() {
+    let road = Road::generate(&loop_settings()).unwrap();
+    // rounder than the square through its points, not quite the circle
+    let length = road.length();
+    assert!(length > 566. && length < 629., "lap of {length}");
+    let checkpoints = road.checkpoints(8);
+    assert_eq!(checkpoints.len(), 8);
+    assert!(checkpoints[0].translation.xz().distance(Vec2::ZERO) < 1e-3);
+    assert!(checkpoints[0].half_extents.x > 6.);
+    for pair in checkpoints.windows(2) {
+        let gap = pair[0].translation.distance(pair[1].translation);
+        assert!(gap > length / 8. * 0.8 && gap <= length / 8. + 1e-3, "checkpoints {gap} apart");
+    }
+    // going around once more ends up back at the start
+    assert!(road.sample_at(length * 2.).translation.distance(road.sample_at(0.).translation) < 1e-3);
+}
+#[test]
+fn collider_follows_the_elevation_and_banking() {
+    let mut app = headless_app();
+    let road = Road::generate(&loop_settings()).unwrap();
+    app.world.spawn((TransformBundle::default(), RigidBody::Fixed, road.collider()));
+    step_frames(&mut app, 1);
+    for distance in [0., 50., 300., 420.] {
+        let sample = road.sample_at(distance);
+        let height = cast_down(&app, sample.translation).expect("no road under the middle");
+        assert!((height - sample.translation.y).abs() < 0.05);
+    }
+    // the raised far side is banked, with its right edge higher than its left
+    let top = road.sample_at(road.length() / 2.);
+    assert!((top.translation.y - 10.).abs() < 1e-3);
+    let offset = top.right * 5.;
+    let right = cast_down(&app, top.translation + offset).unwrap();
+    let left = cast_down(&app, top.translation - offset).unwrap();
+    assert!(right - left > 1.5, "edges at {left} and {right}");
+    assert!(cast_down(&app, top.translation + top.right * 8.).is_none());
+}
+#[test]
+fn car_drives_from_the_spawn_point_over_the_start_line() {
+    let mut app = headless_app();
+    let manifest = TrackManifest::from_road("Loop", loop_settings()).unwrap();
+    assert_eq!(manifest.checkpoints.len(), 8);
+    let road = Road::generate(manifest.road.as_ref().unwrap()).unwrap();
+    app.world.spawn((TransformBundle::default(), RigidBody::Fixed, road.collider()));
+    let spawn = manifest.spawn.transform();
+    let handle = app.world.resource_mut::<Assets<TrackManifest>>().add(manifest);
+    app.insert_resource(ActiveTrack(handle));
+    let car = spawn_test_car(&mut app, &CarSpec::default(), spawn);
+    step_frames(&mut app, 120);
+    assert!(app.world.get::<CarPhysics>(car).unwrap().wheel_infos.iter().all(|wheel| wheel.hit));
+    let mut passed = Vec::new();
+    for _ in 0..300 {
+        app.world.get_mut::<VehicleInput>(car).unwrap().throttle = 1.;
+        step_frames(&mut app, 1);
+        passed.extend(app.world.resource_mut::<Events<CheckpointPassed>>().drain().map(|event| event.checkpoint));
+        if !passed.is_empty() {
+            break;
+        }
+    }
+    assert_eq!(passed, [0]);
+}
@@ -0,0 +1,61 @@
+use bevy::{prelude::*, render::mesh::Indices};
+use bevy_rapier3d::{parry::shape::FeatureId, prelude::*};
+use car_game::{
+    car_spawn::CarSpec,
+    car_suspension::CarPhysics,
+    headless::{headless_app, spawn_test_car, spawn_test_surface, step_frames},
+    surface::{Surface, TriangleSurfaces},
+    vehicle_input::VehicleInput,
+};
+
+/// Speed reached after two seconds of full throttle from a standstill on `surface`.
+fn launch_on(surface: Surface) -> (f32, Vec<Surface>) {
+    let mut app = headless_app();
+    let ground = spawn_test_surface(&mut app, 500.);
+    app.world.entity_mut(ground).insert(surface);
+    let car = spawn_test_car(&mut app, &CarSpec::default(), Transform::from_xyz(0., 1., 0.));
+    step_frames(&mut app, 120);
+    app.world.get_mut::<VehicleInput>(car).unwrap().throttle = 1.;
+    step_frames(&mut app, 120);
+    let speed = app.world.get::<Velocity>(car).unwrap().linvel.length();
+    let surfaces = app.world.get::<CarPhysics>(car).unwrap().wheel_infos.iter().map(|wheel| wheel.surface).collect();
+    (speed, surfaces)
+}
+
+#[test]
+fn wheels_know_the_surface_and_lose_grip_on_ice() {
+    let (asphalt, surfaces) = launch_on(Surface::Asphalt);
+    assert!(surfaces.iter().all(|surface| *surface == Surface::Asphalt));
+    let (ice, surfaces) = launch_on(Surface::Ice);
+    assert!(surfaces.iter().all(|surface| *surface == Surface::Ice));
+    assert!(ice < asphalt * 0.5, "{ice} m/s on ice against {asphalt} m/s on asphalt");
+}
+#[test]
+fn mud_holds_the_car_back() {
+    let (asphalt, _) = launch_on(Surface::Asphalt);
+    let (mud, _) = launch_on(Surface::Mud);
+    assert!(mud < asphalt, "{mud} m/s in mud against {asphalt} m/s on asphalt");
+}
+#[test]
+fn painted_triangles_pick_their_surface() {
+    // two triangles, the first painted green and the second mostly yellow
+    let mut mesh = Mesh::new(bevy::render::render_resource::PrimitiveTopology::TriangleList);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, vec![[0., 0., 0.], [1., 0., 0.], [0., 0., 1.], [1., 0., 1.]]);
+    mesh.insert_attribute(
+        Mesh::ATTRIBUTE_COLOR,
+        vec![[0., 1., 0., 1.], [0.1, 0.9, 0., 1.], [1., 1., 0., 1.], [0.9, 0.9, 0.1, 1.]],
+    );
+    mesh.set_indices(Some(Indices::U32(vec![0, 2, 1, 1, 2, 3])));
+    let triangles = TriangleSurfaces::from_vertex_colors(&mesh).unwrap();
+    assert_eq!(triangles.0, [Surface::Grass, Surface::Sand]);
+    assert_eq!(triangles.get(FeatureId::Face(1)), Some(Surface::Sand));
+    // the back of the first triangle
+    assert_eq!(triangles.get(FeatureId::Face(2)), Some(Surface::Grass));
+    assert_eq!(triangles.get(FeatureId::Vertex(0)), None);
+}
+#[test]
+fn surfaces_are_found_in_names() {
+    assert_eq!(Surface::from_name("Road_Asphalt.001"), Some(Surface::Asphalt));
+    assert_eq!(Surface::from_name("MUD pit"), Some(Surface::Mud));
+    assert_eq!(Surface::from_name("Barrier"), None);
+}
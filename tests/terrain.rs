@@ -0,0 +1,112 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use car_game::{
+    car_spawn::CarSpec,
+    car_suspension::CarPhysics,
+    headless::{headless_app, run_commands, spawn_test_car, step_frames},
+    input_replay::{start_replay, InputRecording, SimulationSeed},
+    terrain::{grow_terrain, GeneratedTerrain, Terrain, TerrainSettings},
+    track::{ActiveTrack, TrackManifest},
+};
+
+fn settings() -> TerrainSettings {
+    TerrainSettings {
+        size: Vec2::new(200., 160.),
+        resolution: 64,
+        height: 30.,
+        feature_size: 60.,
+        ..default()
+    }
+}
+
+#[test]
+fn same_seed_makes_the_same_hills() {
+    let spawn = Vec2::ZERO;
+    let first = Terrain::generate(&settings(), 7, spawn);
+    let again = Terrain::generate(&settings(), 7, spawn);
+    let other = Terrain::generate(&settings(), 8, spawn);
+    assert_eq!(first.heights, again.heights);
+    assert_ne!(first.heights, other.heights);
+    assert_eq!(first.heights.len(), 65 * 65);
+    let (low, high) = first.heights.iter().fold((f32::MAX, f32::MIN), |(low, high), h| (low.min(*h), high.max(*h)));
+    assert!(high - low > 5., "barely any hills, from {low} to {high}");
+}
+#[test]
+fn ground_is_flat_at_the_spawn_point() {
+    let spawn = Vec2::new(30., -20.);
+    let terrain = Terrain::generate(&settings(), 3, spawn);
+    for offset in [Vec2::ZERO, Vec2::new(1.5, 0.), Vec2::new(-1., 1.2)] {
+        let point = spawn + offset;
+        assert!(terrain.height_at(point.x, point.y).abs() < 1e-4);
+    }
+}
+#[test]
+fn heightfield_matches_the_terrain_heights() {
+    let mut app = headless_app();
+    let terrain = Terrain::generate(&settings(), 11, Vec2::ZERO);
+    app.world.spawn((TransformBundle::default(), RigidBody::Fixed, terrain.collider()));
+    step_frames(&mut app, 1);
+    let rapier_context = app.world.resource::<RapierContext>();
+    for (x, z) in [(10.3, -7.9), (-55., 41.2), (80.1, 70.6), (-99., -79.)] {
+        let (_, toi) = rapier_context
+            .cast_ray(Vec3::new(x, 100., z), Vec3::NEG_Y, 200., true, QueryFilter::default())
+            .unwrap();
+        let height = 100. - toi;
+        assert!((height - terrain.height_at(x, z)).abs() < 1e-3, "ray hit {height} at {x}, {z}");
+    }
+}
+/// Heights the ground colliders report under each point, cast from above.
+fn ground_heights(app: &App, points: &[(f32, f32)]) -> Vec<f32> {
+    let rapier_context = app.world.resource::<RapierContext>();
+    points
+        .iter()
+        .map(|&(x, z)| {
+            let (_, toi) = rapier_context
+                .cast_ray(Vec3::new(x, 100., z), Vec3::NEG_Y, 200., true, QueryFilter::default())
+                .unwrap();
+            100. - toi
+        })
+        .collect()
+}
+#[test]
+fn replay_regrows_the_hills_of_its_recorded_seed() {
+    let mut app = headless_app();
+    let manifest = TrackManifest {
+        name: "Hills".to_string(),
+        terrain: Some(settings()),
+        ..default()
+    };
+    let (terrain, mesh) = grow_terrain(&manifest, &settings(), 1);
+    let handle = app.world.resource_mut::<Assets<TrackManifest>>().add(manifest.clone());
+    app.insert_resource(ActiveTrack(handle));
+    let mesh = app.world.resource_mut::<Assets<Mesh>>().add(mesh);
+    app.world.spawn((TransformBundle::default(), mesh, RigidBody::Fixed, terrain.collider(), GeneratedTerrain { seed: 1 }));
+    step_frames(&mut app, 1);
+    let points = [(40.3, -27.9), (-55., 41.2), (80.1, 70.6), (-99., -79.)];
+    let first_run = ground_heights(&app, &points);
+
+    let recording = InputRecording {
+        seed: 42,
+        ..default()
+    };
+    run_commands(&mut app, |commands, asset_server| start_replay(commands, asset_server, recording));
+    step_frames(&mut app, 2);
+    assert_eq!(app.world.resource::<SimulationSeed>().0, 42);
+    let (recorded, _) = grow_terrain(&manifest, &settings(), 42);
+    let replayed = ground_heights(&app, &points);
+    for ((x, z), height) in points.iter().zip(replayed.iter()) {
+        assert!((height - recorded.height_at(*x, *z)).abs() < 1e-3, "ray hit {height} at {x}, {z}");
+    }
+    assert_ne!(first_run, replayed);
+}
+#[test]
+fn car_settles_on_the_terrain() {
+    let mut app = headless_app();
+    let terrain = Terrain::generate(&settings(), 5, Vec2::ZERO);
+    app.world.spawn((TransformBundle::default(), RigidBody::Fixed, terrain.collider()));
+    let car = spawn_test_car(&mut app, &CarSpec::default(), Transform::from_xyz(0., 1., 0.));
+    step_frames(&mut app, 120);
+    let car_physics = app.world.get::<CarPhysics>(car).unwrap();
+    assert!(car_physics.wheel_infos.iter().all(|wheel| wheel.hit));
+    assert!(app.world.get::<Velocity>(car).unwrap().linvel.length() < 0.05);
+}
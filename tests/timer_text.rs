@@ -0,0 +1,15 @@
+use car_game::timer_text::{format_delta, format_time};
+
+#[test]
+fn times_are_formatted_as_minutes_seconds_and_milliseconds() {
+    assert_eq!(format_time(0.), "00:00.000");
+    assert_eq!(format_time(7.25), "00:07.250");
+    assert_eq!(format_time(83.0456), "01:23.046");
+    assert_eq!(format_time(-1.), "00:00.000");
+}
+#[test]
+fn deltas_keep_their_sign() {
+    assert_eq!(format_delta(1.5), "+00:01.500");
+    assert_eq!(format_delta(-0.125), "-00:00.125");
+    assert_eq!(format_delta(0.), "+00:00.000");
+}
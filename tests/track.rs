@@ -0,0 +1,101 @@
+use bevy::prelude::*;
+use car_game::{
+    car_respawn::RespawnSettings,
+    headless::{headless_app, step_frames},
+    race_state::StartTransform,
+    track::{ActiveTrack, SpawnPoint, TrackManifest, TrackMaterial},
+};
+use std::path::Path;
+
+/// Manifest at `path`, loaded by the asset server like the game does.
+fn load_manifest(app: &mut App, path: &str) -> Handle<TrackManifest> {
+    let handle = app.world.resource::<AssetServer>().load(path);
+    for _ in 0..100 {
+        if app.world.resource::<Assets<TrackManifest>>().contains(&handle) {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        step_frames(app, 1);
+    }
+    handle
+}
+
+#[test]
+fn bundled_track_manifest_loads() {
+    let mut app = headless_app();
+    let handle = load_manifest(&mut app, "tracks/racetrack.track.ron");
+    let manifests = app.world.resource::<Assets<TrackManifest>>();
+    let manifest = manifests.get(&handle).expect("the racetrack manifest did not load");
+    assert_eq!(manifest.name, "Racetrack");
+    assert!(!manifest.meshes.is_empty());
+    assert_eq!(manifest.gates().count(), manifest.checkpoints.len() + 1);
+}
+#[test]
+fn practice_manifest_generates_terrain() {
+    let mut app = headless_app();
+    let handle = load_manifest(&mut app, "tracks/practice.track.ron");
+    let manifests = app.world.resource::<Assets<TrackManifest>>();
+    let manifest = manifests.get(&handle).expect("the practice manifest did not load");
+    let terrain = manifest.terrain.as_ref().expect("the practice track has no terrain");
+    assert!(terrain.seed.is_none() && terrain.validate().is_ok());
+    assert_eq!(manifest.gates().count(), 0);
+}
+#[test]
+fn road_manifest_places_its_checkpoints() {
+    let mut app = headless_app();
+    let handle = load_manifest(&mut app, "tracks/hill_loop.track.ron");
+    let manifests = app.world.resource::<Assets<TrackManifest>>();
+    let manifest = manifests.get(&handle).expect("the hill loop manifest did not load");
+    assert_eq!(manifest.checkpoints.len(), 8);
+    assert!(manifest.checkpoints[0].translation.distance(Vec3::new(0., 3., 0.)) < 0.5);
+    // the car starts on the road, behind the start line
+    assert!(manifest.spawn.translation.z > 0. && manifest.spawn.translation.y > 0.);
+}
+#[test]
+fn shipped_manifests_only_name_files_that_exist() {
+    let assets = Path::new(env!("CARGO_MANIFEST_DIR")).join("assets");
+    for entry in std::fs::read_dir(assets.join("tracks")).unwrap() {
+        let path = entry.unwrap().path();
+        let manifest: TrackManifest = ron::de::from_bytes(&std::fs::read(&path).unwrap()).unwrap();
+        let textures = |material: &TrackMaterial| {
+            [&material.base_color_texture, &material.normal_map_texture]
+                .into_iter()
+                .flatten()
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+        let mut files = Vec::new();
+        for mesh in &manifest.meshes {
+            files.push(mesh.mesh.clone());
+            files.extend(textures(&mesh.material));
+        }
+        files.extend(manifest.scenes.iter().map(|scene| scene.scene.clone()));
+        files.extend(manifest.terrain.iter().flat_map(|terrain| textures(&terrain.material)));
+        files.extend(manifest.road.iter().flat_map(|road| textures(&road.material)));
+        for file in files {
+            // labels like `#Mesh0/Primitive0` point inside the file
+            let file = file.split('#').next().unwrap();
+            assert!(assets.join(file).exists(), "{} names {file}, which isn't in assets", path.display());
+        }
+    }
+}
+#[test]
+fn picked_track_sets_the_start_and_kill_height() {
+    let mut app = headless_app();
+    let manifest = TrackManifest {
+        name: "Cliff".to_string(),
+        spawn: SpawnPoint {
+            translation: Vec3::new(10., 5., -20.),
+            rotation: Quat::from_rotation_y(1.),
+        },
+        kill_height: -5.,
+        ..default()
+    };
+    let handle = app.world.resource_mut::<Assets<TrackManifest>>().add(manifest);
+    app.insert_resource(ActiveTrack(handle));
+    step_frames(&mut app, 1);
+    let start = app.world.resource::<StartTransform>().0;
+    assert_eq!(start.translation, Vec3::new(10., 5., -20.));
+    assert!(start.rotation.abs_diff_eq(Quat::from_rotation_y(1.), 1e-6));
+    assert_eq!(app.world.resource::<RespawnSettings>().kill_height, -5.);
+}
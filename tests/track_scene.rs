@@ -0,0 +1,91 @@
+use bevy::{gltf::GltfExtras, prelude::*};
+use bevy_rapier3d::prelude::*;
+use car_game::{
+    headless::{headless_app, step_frames},
+    surface::{Surface, TriangleSurfaces},
+    track_scene::{ColliderKind, SceneColliders},
+};
+
+/// Node named `name` holding a box mesh, the way glTF scenes put primitives under nodes.
+fn spawn_node(world: &mut World, mesh: &Handle<Mesh>, name: &str, extras: Option<&str>) {
+    let mut node = world.spawn((TransformBundle::default(), Name::new(name.to_string())));
+    if let Some(extras) = extras {
+        node.insert(GltfExtras {
+            value: extras.to_string(),
+        });
+    }
+    node.with_children(|parent| {
+        parent.spawn((TransformBundle::default(), mesh.clone(), Name::new("Cube")));
+    });
+}
+/// Mesh entity under the node called `name` in the spawned scene.
+fn mesh_of(app: &mut App, name: &str) -> Entity {
+    let mut nodes = app.world.query::<(&Name, &Children)>();
+    let (_, children) = nodes.iter(&app.world).find(|(node, _)| node.as_str() == name).unwrap();
+    children[0]
+}
+fn collider_of(app: &mut App, name: &str) -> Option<Collider> {
+    let mesh = mesh_of(app, name);
+    app.world.get::<Collider>(mesh).cloned()
+}
+fn surface_of(app: &mut App, name: &str) -> Option<Surface> {
+    let mesh = mesh_of(app, name);
+    app.world.get::<Surface>(mesh).copied()
+}
+
+#[test]
+fn node_names_and_extras_pick_the_collider() {
+    assert_eq!(ColliderKind::for_node("Road.001", None), ColliderKind::TriMesh);
+    assert_eq!(ColliderKind::for_node("Prop_Barrel", None), ColliderKind::Convex);
+    assert_eq!(ColliderKind::for_node("deco-tree", None), ColliderKind::None);
+    // the custom property wins over the name
+    assert_eq!(ColliderKind::for_node("Prop_Sign", Some(r#"{"collider": "none"}"#)), ColliderKind::None);
+    assert_eq!(ColliderKind::for_node("Bush", Some(r#"{"collider": "Convex", "other": null}"#)), ColliderKind::Convex);
+    assert_eq!(ColliderKind::for_node("Wall", Some(r#"{"collider": "glass"}"#)), ColliderKind::TriMesh);
+}
+#[test]
+fn scene_nodes_get_their_colliders_and_surfaces() {
+    let mut app = headless_app();
+    app.register_type::<GltfExtras>();
+    let mesh = app.world.resource_mut::<Assets<Mesh>>().add(shape::Box::new(2., 1., 2.).into());
+    let mut world = World::new();
+    spawn_node(&mut world, &mesh, "Road", None);
+    spawn_node(&mut world, &mesh, "Prop.Barrel", None);
+    spawn_node(&mut world, &mesh, "Deco.Tree", None);
+    spawn_node(&mut world, &mesh, "Ramp", Some(r#"{"collider": "none"}"#));
+    spawn_node(&mut world, &mesh, "Verge_Grass", None);
+    spawn_node(&mut world, &mesh, "Pond", Some(r#"{"surface": "ice"}"#));
+    let mut painted: Mesh = shape::Box::new(2., 1., 2.).into();
+    painted.insert_attribute(Mesh::ATTRIBUTE_COLOR, vec![[0., 1., 0., 1.]; painted.count_vertices()]);
+    let painted = app.world.resource_mut::<Assets<Mesh>>().add(painted);
+    spawn_node(&mut world, &painted, "Verge", Some(r#"{"surface": "painted"}"#));
+    // convex pieces don't keep the mesh's triangles, so there is nothing to paint
+    spawn_node(&mut world, &painted, "Prop.Crate", Some(r#"{"surface": "painted"}"#));
+    let scene = app.world.resource_mut::<Assets<Scene>>().add(Scene::new(world));
+    let root = app
+        .world
+        .spawn((
+            RigidBody::Fixed,
+            SceneBundle {
+                scene,
+                ..default()
+            },
+            SceneColliders,
+        ))
+        .id();
+    step_frames(&mut app, 3);
+
+    assert!(app.world.get::<SceneColliders>(root).is_none());
+    assert!(collider_of(&mut app, "Road").unwrap().as_trimesh().is_some());
+    assert!(collider_of(&mut app, "Prop.Barrel").unwrap().as_compound().is_some());
+    assert!(collider_of(&mut app, "Deco.Tree").is_none());
+    assert!(collider_of(&mut app, "Ramp").is_none());
+    assert_eq!(surface_of(&mut app, "Road"), None);
+    assert_eq!(surface_of(&mut app, "Verge_Grass"), Some(Surface::Grass));
+    assert_eq!(surface_of(&mut app, "Pond"), Some(Surface::Ice));
+    let verge = mesh_of(&mut app, "Verge");
+    assert!(app.world.get::<TriangleSurfaces>(verge).is_some_and(|triangles| triangles.0.iter().all(|surface| *surface == Surface::Grass)));
+    let crate_mesh = mesh_of(&mut app, "Prop.Crate");
+    assert!(app.world.get::<TriangleSurfaces>(crate_mesh).is_none());
+    assert!(collider_of(&mut app, "Prop.Crate").unwrap().as_compound().is_some());
+}
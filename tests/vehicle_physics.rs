@@ -0,0 +1,280 @@
+use std::time::Duration;
+
+use bevy::{
+    input::{
+        gamepad::{GamepadButtonChangedEvent, GamepadConnection, GamepadConnectionEvent, GamepadEvent, GamepadInfo},
+        keyboard::KeyboardInput,
+        ButtonState,
+    },
+    prelude::*,
+    time::TimeUpdateStrategy,
+};
+use bevy_rapier3d::prelude::*;
+use car_game::{
+    car_controls::CarController,
+    car_spawn::CarSpec,
+    car_suspension::{Axle, CarPhysics},
+    car_tuning::CarTuning,
+    headless::{headless_app, headless_vehicle_app, spawn_test_car, spawn_test_surface, step_frames},
+    race_state::RaceState,
+    vehicle_input::{HumanDriver, InputAction, InputBindings, InputBindingsFile, VehicleInput},
+};
+
+/// Headless app with a car that has settled on a flat surface.
+fn settled_car() -> (App, Entity) {
+    let mut app = headless_app();
+    spawn_test_surface(&mut app, 500.);
+    let car = spawn_test_car(&mut app, &CarSpec::default(), Transform::from_xyz(0., 1., 0.));
+    step_frames(&mut app, 120);
+    (app, car)
+}
+fn input(app: &mut App, car: Entity) -> Mut<'_, VehicleInput> {
+    app.world.get_mut::<VehicleInput>(car).unwrap()
+}
+fn transform(app: &App, car: Entity) -> Transform {
+    *app.world.get::<Transform>(car).unwrap()
+}
+fn velocity(app: &App, car: Entity) -> Velocity {
+    *app.world.get::<Velocity>(car).unwrap()
+}
+fn forward_speed(app: &App, car: Entity) -> f32 {
+    velocity(app, car).linvel.dot(transform(app, car).forward())
+}
+/// Updates the app once with the clock moved on by `steps` physics steps, which need not
+/// be whole, so a frame can run the fixed update any number of times.
+fn advance(app: &mut App, steps: f32) {
+    let period = app.world.resource::<FixedTime>().period;
+    let TimeUpdateStrategy::ManualInstant(now) = *app.world.resource::<TimeUpdateStrategy>() else {
+        panic!("the headless app steps time by hand");
+    };
+    app.insert_resource(TimeUpdateStrategy::ManualInstant(now + Duration::from_secs_f32(period.as_secs_f32() * steps)));
+    app.update();
+}
+fn send_key(app: &mut App, key_code: KeyCode, state: ButtonState) {
+    app.world.send_event(KeyboardInput {
+        scan_code: 0,
+        key_code: Some(key_code),
+        state,
+    });
+}
+fn automatic(app: &App, car: Entity) -> bool {
+    app.world.get::<CarController>(car).unwrap().drivetrain.automatic
+}
+
+#[test]
+fn car_comes_to_rest_on_its_wheels() {
+    let (app, car) = settled_car();
+    let car_physics = app.world.get::<CarPhysics>(car).unwrap();
+    assert!(car_physics.wheel_infos.iter().all(|wheel| wheel.hit));
+    assert!(velocity(&app, car).linvel.length() < 0.05);
+    assert!(transform(&app, car).up().dot(Vec3::Y) > 0.999);
+    let height = transform(&app, car).translation.y;
+    assert!(height > 0.3 && height < 1., "body rests at {height}");
+}
+
+#[test]
+fn suspension_carries_the_weight_of_the_car() {
+    let (app, car) = settled_car();
+    let rapier_context = app.world.resource::<RapierContext>();
+    let body = rapier_context.entity2body().get(&car).unwrap();
+    let mass = rapier_context.bodies.get(*body).unwrap().mass();
+    let weight = mass * 9.81;
+    let load: f32 = app.world.get::<CarPhysics>(car).unwrap().wheel_infos.iter().map(|wheel| wheel.load).sum();
+    assert!((load - weight).abs() < weight * 0.05, "suspension load {load} for a weight of {weight}");
+}
+
+#[test]
+fn throttle_drives_the_car_forward() {
+    let (mut app, car) = settled_car();
+    input(&mut app, car).throttle = 1.;
+    step_frames(&mut app, 180);
+    assert!(forward_speed(&app, car) > 5.);
+    assert!(transform(&app, car).translation.z < -5.);
+    assert!(transform(&app, car).translation.x.abs() < 0.1);
+    assert!(app.world.get::<CarController>(car).unwrap().drivetrain.gear >= 1);
+}
+
+#[test]
+fn reloaded_tuning_with_fewer_gears_leaves_the_car_in_one_it_has() {
+    let (mut app, car) = settled_car();
+    let handle = app.world.resource_mut::<Assets<CarTuning>>().add(CarTuning::default());
+    app.world.get_mut::<CarPhysics>(car).unwrap().tuning = handle.clone();
+    step_frames(&mut app, 1);
+    app.world.get_mut::<CarController>(car).unwrap().drivetrain.gear = 5;
+    let mut tunings = app.world.resource_mut::<Assets<CarTuning>>();
+    tunings.get_mut(&handle).unwrap().drivetrain.gearbox.forward_ratios = vec![2.9, 2.0];
+    input(&mut app, car).throttle = 1.;
+    step_frames(&mut app, 30);
+    let gear = app.world.get::<CarController>(car).unwrap().drivetrain.gear;
+    assert!((1..=2).contains(&gear), "in gear {gear}");
+    assert!(forward_speed(&app, car) > 0.1);
+}
+#[test]
+fn tuning_without_forward_gears_is_refused() {
+    let mut tuning = CarTuning::default();
+    tuning.drivetrain.gearbox.forward_ratios.clear();
+    assert!(tuning.validate().is_err());
+    assert!(CarTuning::default().validate().is_ok());
+}
+#[test]
+fn brake_stops_the_car() {
+    let (mut app, car) = settled_car();
+    input(&mut app, car).throttle = 1.;
+    step_frames(&mut app, 120);
+    let mut input = input(&mut app, car);
+    input.throttle = 0.;
+    input.brake = 1.;
+    let stopped_after = (0..300).find(|_| {
+        step_frames(&mut app, 1);
+        forward_speed(&app, car) < 0.5
+    });
+    assert!(stopped_after.is_some_and(|frames| frames < 240), "stopped after {stopped_after:?} frames");
+}
+
+#[test]
+fn brake_at_standstill_reverses() {
+    let (mut app, car) = settled_car();
+    input(&mut app, car).brake = 1.;
+    step_frames(&mut app, 180);
+    assert_eq!(app.world.get::<CarController>(car).unwrap().drivetrain.gear, -1);
+    assert!(forward_speed(&app, car) < -1.);
+    assert!(transform(&app, car).translation.z > 1.);
+}
+
+#[test]
+fn steering_left_turns_the_car_left() {
+    let (mut app, car) = settled_car();
+    let mut input = input(&mut app, car);
+    input.throttle = 1.;
+    input.steer = -1.;
+    step_frames(&mut app, 180);
+    assert!(velocity(&app, car).angvel.y > 0.1);
+    assert!(transform(&app, car).translation.x < -1.);
+    assert!(transform(&app, car).up().dot(Vec3::Y) > 0.9);
+}
+
+#[test]
+fn steering_at_a_standstill_only_turns_the_wheels() {
+    let (mut app, car) = settled_car();
+    let heading = transform(&app, car).forward();
+    input(&mut app, car).steer = -1.;
+    step_frames(&mut app, 120);
+    // the tires steer the car, nothing twists the body around on the spot
+    let car_physics = app.world.get::<CarPhysics>(car).unwrap();
+    assert!(car_physics.wheel_infos.iter().filter(|wheel| wheel.axle == Axle::Front).all(|wheel| wheel.steer_angle > 0.3));
+    assert!(velocity(&app, car).angvel.length() < 0.01);
+    assert!(transform(&app, car).forward().dot(heading) > 0.9999);
+}
+
+#[test]
+fn handbrake_locks_the_rear_wheels() {
+    let (mut app, car) = settled_car();
+    input(&mut app, car).throttle = 1.;
+    step_frames(&mut app, 120);
+    let mut input = input(&mut app, car);
+    input.throttle = 0.;
+    input.handbrake = true;
+    step_frames(&mut app, 30);
+    let car_physics = app.world.get::<CarPhysics>(car).unwrap();
+    for wheel in car_physics.wheel_infos.iter() {
+        match wheel.axle {
+            Axle::Rear => {
+                assert_eq!(wheel.spin, 0.);
+                assert!(wheel.slip_ratio < -0.9, "rear wheel slips {}", wheel.slip_ratio);
+            }
+            Axle::Front => assert!(wheel.slip_ratio.abs() < 0.1, "front wheel slips {}", wheel.slip_ratio),
+        }
+    }
+    assert!(forward_speed(&app, car) > 1.);
+}
+
+#[test]
+fn button_press_acts_once_however_many_fixed_steps_a_frame_runs() {
+    let (mut app, car) = settled_car();
+    app.world.entity_mut(car).insert(HumanDriver::default());
+    let toggle = app.world.resource::<InputBindings>().keyboard.toggle_automatic;
+    assert!(automatic(&app, car));
+    // pressed and released over frames too short for a fixed step, then acted on once
+    send_key(&mut app, toggle, ButtonState::Pressed);
+    advance(&mut app, 0.4);
+    send_key(&mut app, toggle, ButtonState::Released);
+    advance(&mut app, 0.4);
+    assert!(automatic(&app, car));
+    advance(&mut app, 3.);
+    assert!(!automatic(&app, car));
+    // pressed during a frame running several fixed steps
+    send_key(&mut app, toggle, ButtonState::Pressed);
+    advance(&mut app, 3.);
+    send_key(&mut app, toggle, ButtonState::Released);
+    advance(&mut app, 3.);
+    assert!(automatic(&app, car));
+    assert!(!app.world.get::<VehicleInput>(car).unwrap().toggle_automatic);
+}
+
+#[test]
+fn vehicle_plugin_alone_leaves_the_player_in_control() {
+    let mut app = headless_vehicle_app();
+    assert!(app.world.get_resource::<State<RaceState>>().is_none());
+    spawn_test_surface(&mut app, 500.);
+    let car = spawn_test_car(&mut app, &CarSpec::default(), Transform::from_xyz(0., 1., 0.));
+    app.world.entity_mut(car).insert(HumanDriver::default());
+    step_frames(&mut app, 120);
+    let throttle = app.world.resource::<InputBindings>().keyboard.throttle;
+    app.world.resource_mut::<Input<KeyCode>>().press(throttle);
+    step_frames(&mut app, 60);
+    assert_eq!(app.world.get::<VehicleInput>(car).unwrap().throttle, 1.);
+    assert!(forward_speed(&app, car) > 1.);
+}
+#[test]
+fn each_gamepad_drives_its_own_car() {
+    let mut app = headless_vehicle_app();
+    spawn_test_surface(&mut app, 500.);
+    let cars = [-5., 5.].map(|x| {
+        let car = spawn_test_car(&mut app, &CarSpec::default(), Transform::from_xyz(x, 1., 0.));
+        app.world.entity_mut(car).insert(HumanDriver::default());
+        car
+    });
+    for id in 0..2 {
+        let info = GamepadInfo { name: format!("Pad {id}") };
+        let connected = GamepadConnectionEvent::new(Gamepad::new(id), GamepadConnection::Connected(info));
+        app.world.send_event(GamepadEvent::Connection(connected));
+    }
+    step_frames(&mut app, 1);
+    let pads = cars.map(|car| app.world.get::<HumanDriver>(car).unwrap().gamepad);
+    assert!(pads[0].is_some() && pads[1].is_some() && pads[0] != pads[1], "{pads:?}");
+    let throttle = app.world.resource::<InputBindings>().gamepad.throttle;
+    let pressed = GamepadButtonChangedEvent::new(pads[0].unwrap(), throttle, 1.);
+    app.world.send_event(GamepadEvent::Button(pressed));
+    step_frames(&mut app, 1);
+    assert_eq!(app.world.get::<VehicleInput>(cars[0]).unwrap().throttle, 1.);
+    assert_eq!(app.world.get::<VehicleInput>(cars[1]).unwrap().throttle, 0.);
+}
+#[test]
+fn rebound_controls_are_saved() {
+    let path = std::env::temp_dir().join(format!("car_game_{}.bindings.ron", std::process::id()));
+    let mut app = headless_vehicle_app();
+    app.insert_resource(InputBindingsFile(path.clone()));
+    let keys = [
+        KeyCode::Key1,
+        KeyCode::Key2,
+        KeyCode::Key3,
+        KeyCode::Key4,
+        KeyCode::Key5,
+        KeyCode::Key6,
+        KeyCode::Key7,
+        KeyCode::Key8,
+        KeyCode::Key9,
+    ];
+    assert_eq!(keys.len(), InputAction::ALL.len());
+    for key in [KeyCode::F1].into_iter().chain(keys) {
+        send_key(&mut app, key, ButtonState::Pressed);
+        step_frames(&mut app, 1);
+        send_key(&mut app, key, ButtonState::Released);
+        step_frames(&mut app, 1);
+    }
+    let saved = InputBindings::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(saved.keyboard.throttle, KeyCode::Key1);
+    assert_eq!(saved.keyboard.toggle_automatic, KeyCode::Key9);
+    assert_eq!(app.world.resource::<InputBindings>().keyboard.throttle, KeyCode::Key1);
+}